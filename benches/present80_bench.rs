@@ -23,7 +23,7 @@ macro_rules! bench_encrypt_or_decrypt {
             let key_bytes = hex::decode($k).unwrap();
             let key = present80::Key::new(&key_bytes[..]);
 
-            b.iter(|| present80::$f(&input[..], key));
+            b.iter(|| present80::$f(&input[..], key.clone()));
         }
     };
 }
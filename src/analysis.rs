@@ -0,0 +1,208 @@
+use std::error;
+use std::fmt;
+
+pub const SBOX_SIZE: usize = 16;
+
+// Rows are indexed by the input difference or mask and columns by the output one.
+pub type Table = [[i32; SBOX_SIZE]; SBOX_SIZE];
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidEntry(u8),
+    NotBijective,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidEntry(x) => write!(f, "S-box entry {:#x} does not fit in 4 bits", x),
+            Error::NotBijective => write!(f, "S-box is not a permutation"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+fn validate(sbox: &[u8; SBOX_SIZE]) -> Result<(), Error> {
+    match sbox.iter().find(|&&x| x as usize >= SBOX_SIZE) {
+        Some(&x) => Err(Error::InvalidEntry(x)),
+        None => Ok(()),
+    }
+}
+
+// A const fn, so that the inverse of a constant S-box can be a constant too. Loops stand in for
+// iterators and ?, which a const fn cannot use.
+pub const fn inverse(sbox: &[u8; SBOX_SIZE]) -> Result<[u8; SBOX_SIZE], Error> {
+    let mut x = 0;
+    while x < SBOX_SIZE {
+        if sbox[x] as usize >= SBOX_SIZE {
+            return Err(Error::InvalidEntry(sbox[x]));
+        }
+        x += 1;
+    }
+
+    let mut inverse = [0u8; SBOX_SIZE];
+    let mut seen = [false; SBOX_SIZE];
+    let mut x = 0;
+    while x < SBOX_SIZE {
+        let y = sbox[x] as usize;
+        if seen[y] {
+            return Err(Error::NotBijective);
+        }
+        seen[y] = true;
+        inverse[y] = x as u8;
+        x += 1;
+    }
+    Ok(inverse)
+}
+
+fn parity(x: usize) -> i32 {
+    (x.count_ones() & 1) as i32
+}
+
+// DDT[a][b] counts the inputs x with S(x) ^ S(x ^ a) = b.
+pub fn ddt(sbox: &[u8; SBOX_SIZE]) -> Result<Table, Error> {
+    validate(sbox)?;
+
+    let mut table = [[0; SBOX_SIZE]; SBOX_SIZE];
+    for (a, row) in table.iter_mut().enumerate() {
+        for x in 0..SBOX_SIZE {
+            row[(sbox[x] ^ sbox[x ^ a]) as usize] += 1;
+        }
+    }
+    Ok(table)
+}
+
+// LAT[a][b] counts the inputs x with a.x = b.S(x), minus half of them, so that 0 means no bias.
+pub fn lat(sbox: &[u8; SBOX_SIZE]) -> Result<Table, Error> {
+    validate(sbox)?;
+
+    let mut table = [[0; SBOX_SIZE]; SBOX_SIZE];
+    for (a, row) in table.iter_mut().enumerate() {
+        for (b, entry) in row.iter_mut().enumerate() {
+            let matches = (0..SBOX_SIZE)
+                .filter(|&x| parity(a & x) == parity(b & sbox[x] as usize))
+                .count() as i32;
+            *entry = matches - SBOX_SIZE as i32 / 2;
+        }
+    }
+    Ok(table)
+}
+
+// BCT[a][b] counts the inputs x with S^-1(S(x) ^ b) ^ S^-1(S(x ^ a) ^ b) = a, as defined by
+// Cid et al. for boomerang attacks. It is only defined for permutations.
+pub fn bct(sbox: &[u8; SBOX_SIZE]) -> Result<Table, Error> {
+    let sbox_inv = inverse(sbox)?;
+
+    let mut table = [[0; SBOX_SIZE]; SBOX_SIZE];
+    for (a, row) in table.iter_mut().enumerate() {
+        for (b, entry) in row.iter_mut().enumerate() {
+            *entry = (0..SBOX_SIZE)
+                .filter(|&x| {
+                    let y = sbox_inv[(sbox[x] as usize) ^ b] ^ sbox_inv[(sbox[x ^ a] as usize) ^ b];
+                    y as usize == a
+                })
+                .count() as i32;
+        }
+    }
+    Ok(table)
+}
+
+// The smallest number of active input and output bits over all pairs of distinct inputs.
+pub fn differential_branch_number(sbox: &[u8; SBOX_SIZE]) -> Result<u32, Error> {
+    validate(sbox)?;
+
+    let mut branch_number = u32::MAX;
+    for x in 0..SBOX_SIZE {
+        for y in x + 1..SBOX_SIZE {
+            let weight = (x ^ y).count_ones() + (sbox[x] ^ sbox[y]).count_ones();
+            branch_number = branch_number.min(weight);
+        }
+    }
+    Ok(branch_number)
+}
+
+// The smallest number of active input and output mask bits over all biased approximations.
+pub fn linear_branch_number(sbox: &[u8; SBOX_SIZE]) -> Result<u32, Error> {
+    let table = lat(sbox)?;
+
+    let mut branch_number = u32::MAX;
+    for (a, row) in table.iter().enumerate() {
+        for (b, &bias) in row.iter().enumerate() {
+            if (a, b) != (0, 0) && bias != 0 {
+                branch_number = branch_number.min(a.count_ones() + b.count_ones());
+            }
+        }
+    }
+    Ok(branch_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::S;
+    use super::*;
+
+    #[test]
+    fn test_ddt() {
+        let table = ddt(&S).unwrap();
+        assert_eq!(16, table[0][0]);
+        for row in table.iter() {
+            assert_eq!(16, row.iter().sum::<i32>());
+        }
+        // PRESENT's S-box is differentially 4-uniform.
+        let max = table.iter().skip(1).flat_map(|row| row.iter()).max();
+        assert_eq!(Some(&4), max);
+        assert_eq!(4, table[1][9]);
+        assert_eq!(0, table[1][1]);
+    }
+
+    #[test]
+    fn test_lat() {
+        let table = lat(&S).unwrap();
+        assert_eq!(8, table[0][0]);
+        assert!(table[0].iter().skip(1).all(|&bias| bias == 0));
+        let max = table
+            .iter()
+            .skip(1)
+            .flat_map(|row| row.iter())
+            .map(|bias| bias.abs())
+            .max();
+        assert_eq!(Some(4), max);
+    }
+
+    #[test]
+    fn test_bct() {
+        let ddt = ddt(&S).unwrap();
+        let bct = bct(&S).unwrap();
+        for a in 0..SBOX_SIZE {
+            assert_eq!(16, bct[a][0]);
+            assert_eq!(16, bct[0][a]);
+            for b in 0..SBOX_SIZE {
+                assert!(bct[a][b] >= ddt[a][b]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_branch_numbers() {
+        assert_eq!(Ok(3), differential_branch_number(&S));
+        assert_eq!(Ok(2), linear_branch_number(&S));
+
+        let identity: Vec<u8> = (0..16).collect();
+        let mut sbox = [0u8; SBOX_SIZE];
+        sbox.copy_from_slice(&identity);
+        assert_eq!(Ok(2), differential_branch_number(&sbox));
+        assert_eq!(Ok(2), linear_branch_number(&sbox));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut sbox = S;
+        sbox[3] = 0x10;
+        assert_eq!(Err(Error::InvalidEntry(0x10)), ddt(&sbox));
+
+        sbox[3] = sbox[4];
+        assert!(ddt(&sbox).is_ok());
+        assert_eq!(Err(Error::NotBijective), bct(&sbox));
+    }
+}
@@ -0,0 +1,317 @@
+use std::error;
+use std::fmt;
+
+use getrandom;
+use rayon::prelude::*;
+
+use super::experiment::Rng;
+use super::reduced::{self, Rounds};
+use super::trail::{Kind, TargetSearch, Trail};
+use super::{present80, BlockCipher, PRESENT};
+
+// The trails cover all but the last round, and trails over 5 rounds already take too long to
+// search for and to collect pairs for.
+pub const MAX_ROUNDS: usize = 5;
+
+const NUM_NIBBLES: usize = 16;
+
+// Votes for every guess of every nibble of the whitening key, before the permutation.
+pub type Votes = [[u64; 16]; NUM_NIBBLES];
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidRounds(usize),
+    TooManyPairs(u64),
+    KeyNotFound(u64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidRounds(num_rounds) => write!(
+                f,
+                "cannot attack {} rounds, the attack needs between 2 and {}",
+                num_rounds, MAX_ROUNDS
+            ),
+            Error::TooManyPairs(right_pairs) => write!(
+                f,
+                "{} right pairs for each trail need more chosen plaintexts than can be counted",
+                right_pairs
+            ),
+            Error::KeyNotFound(last_round_key) => write!(
+                f,
+                "no key matches the last round key {:016x}, try more pairs",
+                last_round_key
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// A last-round attack on PRESENT-80 reduced to num_rounds rounds followed by the key whitening.
+//
+// Each differential trail over the first num_rounds - 1 rounds predicts the difference going
+// into the S-boxes of the last round. Peeling off the whitening key and the last round for a
+// guess of one nibble of the key gives that difference back for the right pairs whenever the
+// guess is right, so the nibbles can be guessed one at a time. Trails are picked until every
+// nibble of the key is covered, and the 16 bits of the key register that the last round key
+// leaves out are found by brute force.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attack {
+    pub num_rounds: usize,
+    // The number of pairs for each trail is chosen so that this many follow it on average.
+    pub right_pairs: u64,
+    pub seed: u64,
+}
+
+pub struct Recovery {
+    pub trails: Vec<Trail>,
+    pub votes: Votes,
+    pub last_round_key: u64,
+    pub key: present80::Key,
+    pub chosen_plaintexts: u64,
+}
+
+fn nibble(state: u64, i: usize) -> usize {
+    ((state >> (4 * i)) & 0xf) as usize
+}
+
+fn active_nibbles(state: u64) -> impl Iterator<Item = usize> {
+    (0..NUM_NIBBLES).filter(move |&i| nibble(state, i) != 0)
+}
+
+impl Attack {
+    pub fn new(num_rounds: usize) -> Attack {
+        let mut seed = [0u8; 8];
+        getrandom::getrandom(&mut seed).expect("error seeding attack");
+
+        Attack {
+            num_rounds,
+            right_pairs: 16,
+            seed: u64::from_be_bytes(seed),
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.num_rounds < 2 || self.num_rounds > MAX_ROUNDS {
+            return Err(Error::InvalidRounds(self.num_rounds));
+        }
+        Ok(())
+    }
+
+    // The cheapest trail into each bit not covered yet. Covering single nibbles is not enough:
+    // when only the lowest bit of a nibble differs, guesses that differ by 5 always get the same
+    // votes.
+    pub fn trails(&self) -> Result<Vec<Trail>, Error> {
+        self.validate()?;
+
+        let mut search = TargetSearch::new(Kind::Differential, self.num_rounds - 1);
+        let mut trails: Vec<Trail> = Vec::new();
+        let mut covered = 0u64;
+        while covered != !0 {
+            let target = 1 << (!covered).trailing_zeros();
+            let trail = search.best_trail_to(target);
+            covered |= trail.output();
+            trails.push(trail);
+        }
+        Ok(trails)
+    }
+
+    fn num_pairs(&self, trail: &Trail) -> Result<u64, Error> {
+        1u64.checked_shl(trail.weight().ceil() as u32)
+            .and_then(|n| n.checked_mul(self.right_pairs))
+            .ok_or(Error::TooManyPairs(self.right_pairs))
+    }
+
+    fn vote<C: BlockCipher>(
+        &self,
+        oracle: &C,
+        index: usize,
+        trail: &Trail,
+        num_pairs: u64,
+    ) -> Votes {
+        let input = trail.rounds[0].input;
+        let output = trail.output();
+        let active: Vec<usize> = active_nibbles(output).collect();
+        let inactive = active
+            .iter()
+            .fold(!0u64, |mask, &i| mask & !(0xf << (4 * i)));
+        let sbox_inv = PRESENT.sbox_inv();
+
+        let mut votes = [[0; 16]; NUM_NIBBLES];
+        let mut rng = Rng::new(self.seed, index as u64);
+        for _ in 0..num_pairs {
+            let plaintext = rng.next();
+            let x = PRESENT.inv_p_layer(oracle.encrypt_state(plaintext));
+            let y = PRESENT.inv_p_layer(oracle.encrypt_state(plaintext ^ input));
+
+            // Right pairs can only differ where the last round has active S-boxes.
+            if (x ^ y) & inactive != 0 {
+                continue;
+            }
+            for &i in active.iter() {
+                for (guess, count) in votes[i].iter_mut().enumerate() {
+                    let a = sbox_inv[nibble(x, i) ^ guess];
+                    let b = sbox_inv[nibble(y, i) ^ guess];
+                    if (a ^ b) as usize == nibble(output, i) {
+                        *count += 1;
+                    }
+                }
+            }
+        }
+        votes
+    }
+
+    // Tries every value of the bits of the key register missing from the last round key, running
+    // the key schedule back to the master key for each.
+    fn search_key<C: BlockCipher>(
+        &self,
+        oracle: &C,
+        last_round_key: u64,
+    ) -> Result<present80::Key, Error> {
+        let mut rng = Rng::new(self.seed, u64::MAX);
+        let known: Vec<(u64, u64)> = (0..2)
+            .map(|_| {
+                let plaintext = rng.next();
+                (plaintext, oracle.encrypt_state(plaintext))
+            })
+            .collect();
+
+        let mut bytes = [0u8; present80::KEY_LENGTH_IN_BYTES];
+        bytes[..8].copy_from_slice(&last_round_key.to_be_bytes());
+        let found = (0..=u16::MAX).into_par_iter().find_any(|&low| {
+            let mut bytes = bytes;
+            bytes[8..].copy_from_slice(&low.to_be_bytes());
+            let key = present80::Key::new(&bytes);
+            super::zeroize(&mut bytes);
+
+            let key = present80::master_key(key, self.num_rounds + 1);
+            let cipher = reduced::Cipher::new80(key, Rounds::new(self.num_rounds))
+                .expect("the rounds were validated");
+            known
+                .iter()
+                .all(|&(plaintext, ciphertext)| cipher.encrypt_state(plaintext) == ciphertext)
+        });
+
+        let low = found.ok_or(Error::KeyNotFound(last_round_key))?;
+        bytes[8..].copy_from_slice(&low.to_be_bytes());
+        let key = present80::master_key(present80::Key::new(&bytes), self.num_rounds + 1);
+        super::zeroize(&mut bytes);
+        Ok(key)
+    }
+
+    // The oracle is the cipher under attack, which encrypts chosen plaintexts under the unknown
+    // key with Rounds::new(num_rounds).
+    pub fn recover_key<C: BlockCipher + Sync>(&self, oracle: &C) -> Result<Recovery, Error> {
+        let trails = self.trails()?;
+        let num_pairs = trails
+            .iter()
+            .map(|trail| self.num_pairs(trail))
+            .collect::<Result<Vec<u64>, Error>>()?;
+        let chosen_plaintexts = num_pairs
+            .iter()
+            .try_fold(2u64, |total, &n| total.checked_add(n.checked_mul(2)?))
+            .ok_or(Error::TooManyPairs(self.right_pairs))?;
+
+        let all_votes: Vec<Votes> = trails
+            .par_iter()
+            .zip(num_pairs.par_iter())
+            .enumerate()
+            .map(|(index, (trail, &n))| self.vote(oracle, index, trail, n))
+            .collect();
+        let mut votes = [[0; 16]; NUM_NIBBLES];
+        for trail_votes in all_votes.iter() {
+            for (total, trail_votes) in votes.iter_mut().zip(trail_votes.iter()) {
+                for (count, trail_count) in total.iter_mut().zip(trail_votes.iter()) {
+                    *count += trail_count;
+                }
+            }
+        }
+
+        // The best guesses make up the whitening key as it is before the permutation.
+        let mut key = 0u64;
+        for (i, nibble_votes) in votes.iter().enumerate() {
+            let guess = (0..16).max_by_key(|&guess| nibble_votes[guess]).unwrap();
+            key |= (guess as u64) << (4 * i);
+        }
+        let last_round_key = PRESENT.p_layer(key);
+
+        Ok(Recovery {
+            chosen_plaintexts,
+            key: self.search_key(oracle, last_round_key)?,
+            trails,
+            votes,
+            last_round_key,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attack(num_rounds: usize) -> Attack {
+        let mut attack = Attack::new(num_rounds);
+        attack.seed = 42;
+        attack
+    }
+
+    fn oracle(key: &[u8], num_rounds: usize) -> reduced::Cipher {
+        reduced::Cipher::new80(present80::Key::new(key), Rounds::new(num_rounds)).unwrap()
+    }
+
+    #[test]
+    fn test_trails() {
+        let trails = attack(4).trails().unwrap();
+        let covered = trails
+            .iter()
+            .fold(0, |covered, trail| covered | trail.output());
+        assert_eq!(!0, covered);
+        for trail in trails.iter() {
+            assert_eq!(3, trail.rounds.len());
+        }
+    }
+
+    #[test]
+    fn test_recover_key() {
+        let key = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x5a, 0xa5];
+        for &num_rounds in [3, 4].iter() {
+            let oracle = oracle(&key, num_rounds);
+            let recovery = attack(num_rounds).recover_key(&oracle).unwrap();
+            assert_eq!(&key, recovery.key.as_bytes());
+            assert_eq!(oracle.round_keys()[num_rounds], recovery.last_round_key);
+        }
+    }
+
+    #[test]
+    fn test_too_few_pairs() {
+        let oracle = oracle(&[0x42; 10], 3);
+        let mut attack = attack(3);
+        attack.right_pairs = 0;
+        match attack.recover_key(&oracle) {
+            Err(Error::KeyNotFound(_)) => (),
+            _ => panic!("the key should not be found without any pairs"),
+        }
+    }
+
+    #[test]
+    fn test_too_many_pairs() {
+        let mut attack = attack(3);
+        attack.right_pairs = u64::MAX;
+        match attack.recover_key(&oracle(&[0x42; 10], 3)) {
+            Err(Error::TooManyPairs(u64::MAX)) => (),
+            _ => panic!("the number of pairs should overflow"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_rounds() {
+        for &num_rounds in [0, 1, MAX_ROUNDS + 1, 31].iter() {
+            assert_eq!(
+                Err(Error::InvalidRounds(num_rounds)),
+                attack(num_rounds).trails()
+            );
+        }
+    }
+}
@@ -1,384 +1,2118 @@
 extern crate clap;
+extern crate getrandom;
 extern crate hex;
 extern crate present;
+extern crate rayon;
+extern crate rpassword;
 
-use std::io::{self, BufReader, BufWriter, Read, Write};
-use std::fs::File;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::fs::{self, File};
+use std::hint;
+use std::ops;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant, SystemTime};
 
-use clap::{App, Arg, ArgGroup};
-use present::{present128, present80};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+use present::{
+    analysis, attack, cmac, container, encoding, experiment, integral, kdf, keygen, modes,
+    present128, present80, reduced, trace, trail, BlockCipher,
+};
+use rayon::prelude::*;
 
-const MAX_KEY_LENGTH_HEX: usize = 32;
 const MAX_KEY_LENGTH_BINARY: usize = 16;
 
-const BLOCK_SIZE_HEX: usize = 16;
-const BLOCK_SIZE_BINARY: usize = 8;
+const FORMATS: &[&str] = &["binary", "hex", "base64", "base64url", "base32", "armor"];
+
+const ARMOR_LABEL: &str = "PRESENT MESSAGE";
+
+const MANIFEST_FILE_NAME: &str = "MANIFEST";
+
+const MANIFEST_KDF_LABEL: &[u8] = b"PRESENT MANIFEST";
+
+const PROGRESS_INTERVAL_IN_MILLIS: u64 = 100;
+
+const RAW_CHUNK_SIZE_IN_BYTES: u64 = 1 << 16;
 
 enum Format {
     Binary,
     Hex,
+    Base64,
+    Base64Url,
+    Base32,
+    Armor,
 }
 
+#[derive(Clone, Copy)]
 enum KeyLength {
     Key80,
     Key128,
     Auto,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Eax,
+    Ecb,
+    Cbc,
+    Ctr,
+    Cfb,
+    Ofb,
+}
+
+enum Kdf {
+    Pbkdf2,
+    Argon2,
+}
+
+// Key and password bytes are wiped when the buffer holding them is dropped.
+struct Secret(Vec<u8>);
+
+impl ops::Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        present::zeroize(&mut self.0);
+    }
+}
+
+enum KeySource {
+    Bytes(Secret),
+    Password {
+        password: Secret,
+        salt: Option<Vec<u8>>,
+        kdf: Kdf,
+        iterations: u32,
+    },
+}
+
+enum Cipher {
+    Present80(present80::Cipher),
+    Present128(present128::Cipher),
+}
+
 enum InputSource {
     Stdin,
     File(String),
 }
 
+// Output written for an input file gets the permissions of that file.
+enum OutputTarget {
+    Stdout,
+    File {
+        path: PathBuf,
+        force: bool,
+        permissions: Option<fs::Permissions>,
+    },
+}
+
+struct Progress {
+    total: u64,
+    start: Instant,
+    last_update: Option<Instant>,
+}
+
+// The raw modes run over the input one chunk at a time as it is read, so progress can be shown.
+// Every chunk but the last is whole blocks, and iv carries the state of the mode between them.
+struct RawStream<'a, C: BlockCipher + 'a> {
+    cipher: &'a C,
+    mode: Mode,
+    decrypt: bool,
+    iv: Vec<u8>,
+    processed: u64,
+    progress: Option<Progress>,
+}
+
 fn main() {
     let matches = App::new("PRESENT.rs")
         .version("0.1.0")
         .author("Jiayu Yi")
-        .about("Encrypt or decrypt data with the PRESENT block cipher and print to standard output")
-        .arg(Arg::with_name("key")
+        .about("Encrypt, decrypt and authenticate data with the PRESENT block cipher")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .subcommand(SubCommand::with_name("encrypt")
+            .about("Encrypt data and print to standard output")
+            .args(&key_args())
+            .args(&password_args())
+            .group(ArgGroup::with_name("key source")
+                .args(&["key", "key file", "key env", "key fd", "password", "password file"])
+                .required(true))
+            .args(&format_args("binary"))
+            .args(&mode_args())
+            .args(&output_args())
+            .arg(in_place_arg())
+            .args(&progress_args())
+            .arg(Arg::with_name("trace")
+                .long("trace")
+                .conflicts_with("recursive")
+                .help("Print the round keys and intermediate states of every block to standard error, which needs --mode ecb"))
+            .arg(Arg::with_name("trace format")
+                .long("trace-format")
+                .value_name("trace_format")
+                .help("Print each trace as a table or as a line of JSON")
+                .possible_values(&["table", "json"])
+                .default_value("table"))
+            .arg(Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .requires_all(&["FILE", "out dir"])
+                .conflicts_with_all(&["output", "in place"])
+                .help("Encrypt every file under the directory FILE in parallel"))
+            .arg(Arg::with_name("out dir")
+                .long("out-dir")
+                .value_name("out_dir")
+                .takes_value(true)
+                .requires("recursive")
+                .help("Write encrypted files to this directory, along with a MANIFEST of their MACs"))
+            .arg(file_arg()))
+        .subcommand(SubCommand::with_name("decrypt")
+            .about("Decrypt data and print to standard output")
+            .args(&key_args())
+            .args(&password_args())
+            .group(ArgGroup::with_name("key source")
+                .args(&["key", "key file", "key env", "key fd", "password", "password file"])
+                .required(true))
+            .args(&format_args("binary"))
+            .args(&mode_args())
+            .args(&output_args())
+            .arg(in_place_arg())
+            .args(&progress_args())
+            .arg(file_arg()))
+        .subcommand(SubCommand::with_name("keygen")
+            .about("Generate a random key with the operating system's random number generator")
+            .arg(Arg::with_name("key length")
+                .short("l")
+                .long("key-length")
+                .value_name("key_length")
+                .help("Specify whether to generate an 80-bit or 128-bit key")
+                .possible_values(&["80", "128"])
+                .default_value("128"))
+            .arg(output_format_arg("hex"))
+            .args(&output_args()))
+        .subcommand(SubCommand::with_name("mac")
+            .about("Compute the CMAC of data and print to standard output")
+            .args(&key_args())
+            .group(ArgGroup::with_name("key source")
+                .args(&["key", "key file", "key env", "key fd"])
+                .required(true))
+            .args(&format_args("hex"))
+            .args(&output_args())
+            .arg(manifest_arg())
+            .arg(file_arg()))
+        .subcommand(SubCommand::with_name("verify")
+            .about("Verify the CMAC of data, exiting with an error if it does not match")
+            .args(&key_args())
+            .group(ArgGroup::with_name("key source")
+                .args(&["key", "key file", "key env", "key fd"])
+                .required(true))
+            .arg(input_format_arg())
+            .arg(Arg::with_name("tag")
+                .short("t")
+                .long("tag")
+                .value_name("tag")
+                .takes_value(true)
+                .required(true)
+                .help("Hex encoded tag to verify"))
+            .arg(manifest_arg())
+            .arg(file_arg()))
+        .subcommand(SubCommand::with_name("inspect")
+            .about("Print the header of an encrypted container")
+            .arg(input_format_arg())
+            .arg(file_arg()))
+        .subcommand(SubCommand::with_name("bench")
+            .about("Measure encryption throughput")
+            .arg(Arg::with_name("size")
+                .short("s")
+                .long("size")
+                .value_name("size")
+                .default_value("1048576")
+                .help("Specify the number of bytes to encrypt"))
+            .arg(Arg::with_name("key length")
+                .short("l")
+                .long("key-length")
+                .value_name("key_length")
+                .help("Specify whether to use an 80-bit or 128-bit key, or both")
+                .possible_values(&["80", "128", "both"])
+                .default_value("both")))
+        .subcommand(SubCommand::with_name("analyze")
+            .about("Analyse the building blocks of PRESENT")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("sbox")
+                .about("Print the DDT, LAT, BCT and branch numbers of a 4-bit S-box")
+                .arg(Arg::with_name("sbox")
+                    .short("s")
+                    .long("sbox")
+                    .value_name("sbox")
+                    .takes_value(true)
+                    .help("Analyse this S-box instead of PRESENT's, given as 16 hex digits such as c56b90ad3ef84712"))
+                .arg(Arg::with_name("table")
+                    .short("t")
+                    .long("table")
+                    .value_name("table")
+                    .help("Specify what to print")
+                    .possible_values(&["ddt", "lat", "bct", "branch", "all"])
+                    .default_value("all"))
+                .arg(Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .value_name("format")
+                    .help("Print aligned tables or comma-separated values")
+                    .possible_values(&["table", "csv"])
+                    .default_value("table")))
+            .subcommand(SubCommand::with_name("trail")
+                .about("Search for the best differential or linear trail over a number of rounds")
+                .arg(Arg::with_name("rounds")
+                    .short("r")
+                    .long("rounds")
+                    .value_name("rounds")
+                    .required(true)
+                    .help("Specify the number of rounds, bearing in mind that the search takes much longer with every round"))
+                .arg(Arg::with_name("kind")
+                    .short("k")
+                    .long("kind")
+                    .value_name("kind")
+                    .help("Specify whether to search for a differential or linear trail")
+                    .possible_values(&["differential", "linear"])
+                    .default_value("differential")))
+            .subcommand(SubCommand::with_name("experiment")
+                .about("Estimate the probability of a differential or the correlation of a linear approximation with random keys and plaintexts")
+                .arg(Arg::with_name("rounds")
+                    .short("r")
+                    .long("rounds")
+                    .value_name("rounds")
+                    .required(true)
+                    .help("Specify the number of rounds, without the final key whitening"))
+                .arg(Arg::with_name("kind")
+                    .short("k")
+                    .long("kind")
+                    .value_name("kind")
+                    .help("Specify whether to test a differential or linear approximation")
+                    .possible_values(&["differential", "linear"])
+                    .default_value("differential"))
+                .arg(Arg::with_name("input")
+                    .long("input")
+                    .value_name("input")
+                    .requires("output")
+                    .help("Hex encoded input difference or mask, the best trail's if not given"))
+                .arg(Arg::with_name("output")
+                    .long("output")
+                    .value_name("output")
+                    .requires("input")
+                    .help("Hex encoded output difference or mask, the best trail's if not given"))
+                .arg(Arg::with_name("keys")
+                    .long("keys")
+                    .value_name("keys")
+                    .default_value("64")
+                    .help("Specify the number of random keys"))
+                .arg(Arg::with_name("samples")
+                    .long("samples")
+                    .value_name("samples")
+                    .default_value("65536")
+                    .help("Specify the number of plaintexts or plaintext pairs for each key"))
+                .arg(Arg::with_name("key length")
+                    .short("l")
+                    .long("key-length")
+                    .value_name("key_length")
+                    .help("Specify whether to use the 80-bit or 128-bit key schedule")
+                    .possible_values(&["80", "128"])
+                    .default_value("80"))
+                .arg(Arg::with_name("seed")
+                    .long("seed")
+                    .value_name("seed")
+                    .help("Seed the random keys and plaintexts to repeat an experiment")))
+            .subcommand(SubCommand::with_name("integral")
+                .about("Find the output bits that sum to zero over a structure of plaintexts")
+                .arg(Arg::with_name("rounds")
+                    .short("r")
+                    .long("rounds")
+                    .value_name("rounds")
+                    .required(true)
+                    .help("Specify the number of rounds, without the final key whitening"))
+                .arg(Arg::with_name("active")
+                    .short("a")
+                    .long("active")
+                    .value_name("active")
+                    .use_delimiter(true)
+                    .help("Comma-separated nibbles that take every value, counting from 0 at the least significant"))
+                .arg(Arg::with_name("cube")
+                    .short("c")
+                    .long("cube")
+                    .value_name("cube")
+                    .help("Hex encoded mask of the plaintext bits that take every value"))
+                .group(ArgGroup::with_name("structure")
+                    .args(&["active", "cube"])
+                    .required(true))
+                .arg(Arg::with_name("trials")
+                    .long("trials")
+                    .value_name("trials")
+                    .default_value("16")
+                    .help("Specify the number of random keys and constants to try"))
+                .arg(Arg::with_name("key length")
+                    .short("l")
+                    .long("key-length")
+                    .value_name("key_length")
+                    .help("Specify whether to use the 80-bit or 128-bit key schedule")
+                    .possible_values(&["80", "128"])
+                    .default_value("80"))
+                .arg(Arg::with_name("seed")
+                    .long("seed")
+                    .value_name("seed")
+                    .help("Seed the random keys and constants to repeat a run")))
+            .subcommand(SubCommand::with_name("attack")
+                .about("Recover a PRESENT-80 key from chosen plaintexts with a differential last-round attack on a few rounds")
+                .arg(Arg::with_name("rounds")
+                    .short("r")
+                    .long("rounds")
+                    .value_name("rounds")
+                    .required(true)
+                    .help("Specify the number of rounds from 2 to 5, followed by the final key whitening"))
+                .arg(Arg::with_name("key")
+                    .short("K")
+                    .long("key")
+                    .value_name("key")
+                    .help("Hex encoded 80-bit key to attack, a random one if not given"))
+                .arg(Arg::with_name("pairs")
+                    .long("pairs")
+                    .value_name("pairs")
+                    .default_value("16")
+                    .help("Specify the number of right pairs to expect from each trail"))
+                .arg(Arg::with_name("seed")
+                    .long("seed")
+                    .value_name("seed")
+                    .help("Seed the chosen plaintexts to repeat an attack"))))
+        .get_matches();
+
+    match matches.subcommand() {
+        ("encrypt", Some(matches)) => encrypt_command(matches),
+        ("decrypt", Some(matches)) => decrypt_command(matches),
+        ("keygen", Some(matches)) => keygen_command(matches),
+        ("mac", Some(matches)) => mac_command(matches),
+        ("verify", Some(matches)) => verify_command(matches),
+        ("inspect", Some(matches)) => inspect_command(matches),
+        ("bench", Some(matches)) => bench_command(matches),
+        ("analyze", Some(matches)) => analyze_command(matches),
+        _ => unreachable!(),
+    }
+}
+
+fn key_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("key")
             .short("k")
             .long("key")
             .value_name("key")
             .takes_value(true)
-            .help("Hex encoded encryption key"))
-        .arg(Arg::with_name("key file")
+            .help("Hex encoded encryption key"),
+        Arg::with_name("key file")
             .short("K")
             .long("key-file")
             .value_name("key_file")
             .help("Read encryption key from a file")
-            .takes_value(true))
-        .group(ArgGroup::with_name("key source")
-            .args(&["key", "key file"])
-            .required(true))
-        .arg(Arg::with_name("key length")
+            .takes_value(true),
+        Arg::with_name("key env")
+            .long("key-env")
+            .value_name("var")
+            .takes_value(true)
+            .help("Read hex encoded encryption key from an environment variable"),
+        Arg::with_name("key fd")
+            .long("key-fd")
+            .value_name("fd")
+            .takes_value(true)
+            .help("Read encryption key from an open file descriptor"),
+        Arg::with_name("key format")
+            .short("f")
+            .long("key-format")
+            .possible_values(FORMATS)
+            .default_value("binary")
+            .help("Specify key format from file or file descriptor"),
+        Arg::with_name("key length")
             .short("l")
             .long("key-length")
             .value_name("key_length")
-            .help("Specify whether to use an 80-bit or 128-bit key")
+            .help("Specify whether to use an 80-bit or 128-bit key. auto requires a key of exactly 80 or 128 bits")
             .possible_values(&["auto", "80", "128"])
-            .default_value("auto"))
-        .arg(Arg::with_name("input format")
-            .short("I")
-            .long("input-format")
-            .possible_values(&["binary", "hex"])
-            .default_value("binary")
-            .help("Specify input format"))
-        .arg(Arg::with_name("key format")
-            .short("f")
-            .long("key-format")
-            .possible_values(&["binary", "hex"])
-            .default_value("binary")
-            .help("Specify key format from file"))
-        .arg(Arg::with_name("output format")
-            .short("O")
-            .long("output-format")
-            .possible_values(&["binary", "hex"])
-            .default_value("binary")
-            .help("Specify output format"))
-        .arg(Arg::with_name("decrypt")
-            .short("d")
-            .long("decrypt")
-            .help("Decrypt data"))
-        .arg(Arg::with_name("FILE")
-            .help("Encrypt or decrypt the contents of FILE and print to standard output. When no FILE, or when FILE is -, read standard input.")
-            .index(1))
-        .get_matches();
+            .default_value("auto"),
+    ]
+}
+
+fn password_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("password")
+            .short("p")
+            .long("password")
+            .help("Derive the encryption key from a password read from standard input"),
+        Arg::with_name("password file")
+            .long("password-file")
+            .value_name("password_file")
+            .takes_value(true)
+            .help("Derive the encryption key from a password read from a file"),
+        Arg::with_name("salt")
+            .long("salt")
+            .value_name("salt")
+            .takes_value(true)
+            .help("Hex encoded salt for password-based key derivation, random if not given"),
+        Arg::with_name("kdf")
+            .long("kdf")
+            .possible_values(&["pbkdf2", "argon2"])
+            .default_value("pbkdf2")
+            .help("Specify the password-based key derivation function"),
+        Arg::with_name("iterations")
+            .long("iterations")
+            .value_name("iterations")
+            .default_value("10000")
+            .help("Specify the PBKDF2 iteration count"),
+    ]
+}
+
+fn input_format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("input format")
+        .short("I")
+        .long("input-format")
+        .possible_values(FORMATS)
+        .default_value("binary")
+        .help("Specify input format")
+}
+
+fn output_format_arg<'a, 'b>(default: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name("output format")
+        .short("O")
+        .long("output-format")
+        .possible_values(FORMATS)
+        .default_value(default)
+        .help("Specify output format")
+}
+
+fn format_args<'a, 'b>(default_output_format: &'a str) -> Vec<Arg<'a, 'b>> {
+    vec![input_format_arg(), output_format_arg(default_output_format)]
+}
+
+fn output_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("output")
+            .takes_value(true)
+            .help("Write to a file instead of standard output, replacing it only once all output is written"),
+        Arg::with_name("force")
+            .long("force")
+            .help("Overwrite the output file if it already exists"),
+    ]
+}
 
-    let input_format = match matches.value_of("input format").unwrap() {
+fn in_place_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("in place")
+        .long("in-place")
+        .conflicts_with("output")
+        .requires("FILE")
+        .help("Replace FILE with the output")
+}
+
+fn progress_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("progress")
+            .long("progress")
+            .help("Show bytes processed, throughput and time remaining on standard error"),
+        Arg::with_name("stats")
+            .long("stats")
+            .help("Print the number of blocks, elapsed time and throughput on standard error"),
+    ]
+}
+
+fn mode_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("mode")
+            .short("m")
+            .long("mode")
+            .possible_values(&["eax", "ecb", "cbc", "ctr", "cfb", "ofb"])
+            .default_value("eax")
+            .help("Specify the mode of operation. Modes other than eax read and write bare ciphertext without a container header"),
+        Arg::with_name("iv")
+            .long("iv")
+            .alias("nonce")
+            .value_name("iv")
+            .takes_value(true)
+            .help("Hex encoded IV or nonce. If not given, a random one is generated and written before the ciphertext"),
+        Arg::with_name("padding")
+            .long("padding")
+            .possible_values(&["pkcs7", "iso7816", "zero", "none"])
+            .default_value("pkcs7")
+            .help("Specify the padding used by the ecb and cbc modes"),
+        Arg::with_name("raw")
+            .long("raw")
+            .conflicts_with_all(&["mode", "padding"])
+            .help("Read and write bare zero-padded ECB ciphertext, the same as --mode ecb --padding zero"),
+    ]
+}
+
+fn manifest_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("manifest")
+        .long("manifest")
+        .help("Use the MAC key of the MANIFEST written by encrypt --recursive")
+}
+
+fn file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("FILE")
+        .help("Read the contents of FILE. When no FILE, or when FILE is -, read standard input.")
+        .index(1)
+}
+
+fn parse_format(matches: &ArgMatches, name: &str) -> Format {
+    match matches.value_of(name).unwrap() {
         "binary" => Format::Binary,
         "hex" => Format::Hex,
+        "base64" => Format::Base64,
+        "base64url" => Format::Base64Url,
+        "base32" => Format::Base32,
+        "armor" => Format::Armor,
         _ => unreachable!(),
-    };
+    }
+}
 
-    let key_length = match matches.value_of("key length").unwrap() {
+fn parse_input_source(matches: &ArgMatches) -> InputSource {
+    match matches.value_of("FILE") {
+        None => InputSource::Stdin,
+        Some(filename) => match filename {
+            "-" => InputSource::Stdin,
+            _ => InputSource::File(filename.to_string()),
+        },
+    }
+}
+
+fn parse_key_length(matches: &ArgMatches) -> KeyLength {
+    match matches.value_of("key length").unwrap() {
         "auto" => KeyLength::Auto,
         "80" => KeyLength::Key80,
         "128" => KeyLength::Key128,
         _ => unreachable!(),
+    }
+}
+
+fn parse_mode(matches: &ArgMatches) -> Mode {
+    if matches.is_present("raw") {
+        return Mode::Ecb;
+    }
+    match matches.value_of("mode").unwrap() {
+        "eax" => Mode::Eax,
+        "ecb" => Mode::Ecb,
+        "cbc" => Mode::Cbc,
+        "ctr" => Mode::Ctr,
+        "cfb" => Mode::Cfb,
+        "ofb" => Mode::Ofb,
+        _ => unreachable!(),
+    }
+}
+
+fn parse_padding(matches: &ArgMatches) -> modes::Padding {
+    if matches.is_present("raw") {
+        return modes::Padding::Zero;
+    }
+    match matches.value_of("padding").unwrap() {
+        "pkcs7" => modes::Padding::Pkcs7,
+        "iso7816" => modes::Padding::Iso7816,
+        "zero" => modes::Padding::Zero,
+        "none" => modes::Padding::None,
+        _ => unreachable!(),
+    }
+}
+
+fn parse_iv(matches: &ArgMatches, mode: Mode) -> Option<Vec<u8>> {
+    let iv = matches
+        .value_of("iv")
+        .map(|iv| hex::decode(iv).expect("error converting from hex"));
+
+    match (mode, iv) {
+        (Mode::Eax, Some(_)) => exit_with_error("the eax container generates its own nonce"),
+        (Mode::Ecb, Some(_)) => exit_with_error("ecb mode does not use an IV"),
+        (_, iv) => iv,
+    }
+}
+
+fn parse_key_source(matches: &ArgMatches, input_source: &InputSource) -> KeySource {
+    if let Some(key_string) = matches.value_of("key") {
+        KeySource::Bytes(read_key_bytes_from_string(key_string, &Format::Hex))
+    } else if let Some(filename) = matches.value_of("key file") {
+        let key_format = parse_format(matches, "key format");
+        KeySource::Bytes(read_key_bytes_from_file(filename, &key_format))
+    } else if let Some(name) = matches.value_of("key env") {
+        let key_string = env::var(name)
+            .unwrap_or_else(|err| exit_with_error(format!("error reading {}: {}", name, err)));
+        let key_bytes = read_key_bytes_from_string(key_string.trim(), &Format::Hex);
+        present::zeroize(&mut key_string.into_bytes());
+        KeySource::Bytes(key_bytes)
+    } else if let Some(fd) = matches.value_of("key fd") {
+        let fd = fd
+            .parse::<i32>()
+            .unwrap_or_else(|_| exit_with_error("file descriptor must be a non-negative integer"));
+        if let (0, &InputSource::Stdin) = (fd, input_source) {
+            exit_with_error("cannot read both the key and the input from standard input");
+        }
+
+        let key_format = parse_format(matches, "key format");
+        KeySource::Bytes(read_key_bytes_from_fd(fd, &key_format))
+    } else {
+        let password = match matches.value_of("password file") {
+            Some(filename) => read_password_from_file(filename),
+            None => read_password(input_source),
+        };
+        let salt = matches
+            .value_of("salt")
+            .map(|salt| hex::decode(salt).expect("error converting from hex"));
+        let kdf = match matches.value_of("kdf").unwrap() {
+            "pbkdf2" => Kdf::Pbkdf2,
+            "argon2" => Kdf::Argon2,
+            _ => unreachable!(),
+        };
+        let iterations = matches
+            .value_of("iterations")
+            .unwrap()
+            .parse::<u32>()
+            .expect("iterations must be a positive integer");
+
+        KeySource::Password {
+            password,
+            salt,
+            kdf,
+            iterations,
+        }
+    }
+}
+
+// mac and verify only accept raw keys, so there is no key derivation to worry about.
+fn parse_key_cipher(matches: &ArgMatches) -> Cipher {
+    match parse_key_source(matches, &parse_input_source(matches)) {
+        KeySource::Bytes(key_bytes) => {
+            let cipher = make_cipher(parse_key_length(matches), &key_bytes);
+            if matches.is_present("manifest") {
+                manifest_cipher(&cipher, &key_bytes)
+            } else {
+                cipher
+            }
+        }
+        KeySource::Password { .. } => unreachable!(),
+    }
+}
+
+fn parse_output_target(matches: &ArgMatches, input_source: &InputSource) -> OutputTarget {
+    let force = matches.is_present("force");
+    let permissions = match *input_source {
+        InputSource::Stdin => None,
+        InputSource::File(ref filename) => fs::metadata(filename)
+            .map(|metadata| metadata.permissions())
+            .ok(),
     };
 
-    let output_format = match matches.value_of("output format").unwrap() {
-        "binary" => Format::Binary,
-        "hex" => Format::Hex,
+    let path = if matches.is_present("in place") {
+        match *input_source {
+            InputSource::Stdin => exit_with_error("--in-place needs an input FILE"),
+            InputSource::File(ref filename) => {
+                return OutputTarget::File {
+                    path: PathBuf::from(filename),
+                    force: true,
+                    permissions,
+                }
+            }
+        }
+    } else {
+        match matches.value_of("output") {
+            None | Some("-") => return OutputTarget::Stdout,
+            Some(filename) => PathBuf::from(filename),
+        }
+    };
+
+    // Checked up front as well as before renaming, so no work is wasted.
+    if !force && path.exists() {
+        exit_with_error(format!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        ));
+    }
+    OutputTarget::File {
+        path,
+        force,
+        permissions,
+    }
+}
+
+fn open_input(input_source: &InputSource) -> Box<dyn io::BufRead> {
+    match *input_source {
+        InputSource::Stdin => Box::new(io::BufReader::new(io::stdin())),
+        InputSource::File(ref filename) => {
+            Box::new(io::BufReader::new(File::open(filename).expect("file not found")))
+        }
+    }
+}
+
+// Binary input files are streamed, so reading them is part of the work that progress reports.
+// Anything else is read and decoded in full first, to learn its length.
+fn open_sized_input(input_source: &InputSource, input_format: &Format) -> (Box<dyn Read>, u64) {
+    if let (InputSource::File(filename), Format::Binary) = (input_source, input_format) {
+        let file = File::open(filename).expect("file not found");
+        let metadata = file.metadata().expect("error reading file");
+        if metadata.is_file() {
+            return (Box::new(io::BufReader::new(file)), metadata.len());
+        }
+    }
+
+    let data = read_input(&mut open_input(input_source), input_format);
+    let size = data.len() as u64;
+    (Box::new(io::Cursor::new(data)), size)
+}
+
+fn encrypt_command(matches: &ArgMatches) {
+    let input_format = parse_format(matches, "input format");
+    let output_format = parse_format(matches, "output format");
+    let input_source = parse_input_source(matches);
+    let key_length = parse_key_length(matches);
+    let key_source = parse_key_source(matches, &input_source);
+    let mode = parse_mode(matches);
+    let iv = parse_iv(matches, mode);
+    let padding = parse_padding(matches);
+    if matches.is_present("trace") && mode != Mode::Ecb {
+        exit_with_error("--trace needs --mode ecb, where every block is encrypted on its own");
+    }
+
+    let (key_length, key_bytes, kdf) = if mode == Mode::Eax {
+        container_key(key_length, key_source)
+    } else {
+        let (key_length, key_bytes) = raw_key(key_length, key_source);
+        (key_length, key_bytes, container::Kdf::None)
+    };
+    let cipher = make_cipher(key_length, &key_bytes);
+    let encrypt = |data: Vec<u8>| {
+        if mode == Mode::Eax {
+            Ok(container::encrypt(&cipher, cipher.key_size(), kdf.clone(), &data))
+        } else {
+            encrypt_raw(&cipher, mode, iv.clone(), padding, &mut &data[..], None)
+        }
+    };
+
+    if let Some(out_dir) = matches.value_of("out dir") {
+        if iv.is_some() {
+            exit_with_error("--iv cannot be reused for every file with --recursive");
+        }
+        if matches.is_present("progress") || matches.is_present("stats") {
+            exit_with_error("--progress and --stats only work on a single file");
+        }
+        encrypt_directory(
+            Path::new(matches.value_of("FILE").unwrap()),
+            Path::new(out_dir),
+            &manifest_cipher(&cipher, &key_bytes),
+            &encrypt,
+            &input_format,
+            &output_format,
+            matches.is_present("force"),
+        );
+        return;
+    }
+
+    let output_target = parse_output_target(matches, &input_source);
+    let (mut input, size) = open_sized_input(&input_source, &input_format);
+    if matches.is_present("trace") {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data).expect("error reading file");
+        let padded = modes::pad(padding, &data).unwrap_or_else(|err| exit_with_error(err));
+        print_traces(&cipher, &padded, matches.value_of("trace format").unwrap());
+        input = Box::new(io::Cursor::new(data));
+    }
+
+    let start = Instant::now();
+    let progress = parse_progress(matches, size);
+    let output = if mode == Mode::Eax {
+        encrypt_container(&cipher, kdf, &mut input, size, progress)
+    } else {
+        encrypt_raw(&cipher, mode, iv, padding, &mut input, progress)
+            .unwrap_or_else(|err| exit_with_error(err))
+    };
+    if matches.is_present("stats") {
+        print_stats(size, start);
+    }
+    write_output(&output, &output_format, &output_target);
+}
+
+fn decrypt_command(matches: &ArgMatches) {
+    let input_format = parse_format(matches, "input format");
+    let output_format = parse_format(matches, "output format");
+    let input_source = parse_input_source(matches);
+    let key_length = parse_key_length(matches);
+    let key_source = parse_key_source(matches, &input_source);
+    let mode = parse_mode(matches);
+    let iv = parse_iv(matches, mode);
+    let padding = parse_padding(matches);
+    let output_target = parse_output_target(matches, &input_source);
+    let (mut input, size) = open_sized_input(&input_source, &input_format);
+
+    let start = Instant::now();
+    let output = if mode == Mode::Eax {
+        decrypt_container(&mut input, key_length, key_source, matches)
+    } else {
+        let cipher = make_raw_cipher(key_length, key_source);
+        let progress = parse_progress(matches, size);
+        decrypt_raw(&cipher, mode, iv, padding, &mut input, progress)
+    };
+    if matches.is_present("stats") {
+        print_stats(output.len() as u64, start);
+    }
+    write_output(&output, &output_format, &output_target);
+}
+
+fn keygen_command(matches: &ArgMatches) {
+    let output_format = parse_format(matches, "output format");
+    let output_target = parse_output_target(matches, &InputSource::Stdin);
+    let key_size = match matches.value_of("key length").unwrap() {
+        "80" => container::KeySize::Key80,
+        "128" => container::KeySize::Key128,
         _ => unreachable!(),
     };
 
-    let decrypt_mode = matches.is_present("decrypt");
+    let key_bytes = Secret(keygen::generate(key_size).unwrap_or_else(|err| exit_with_error(err)));
+    let encoded = Secret(match output_format {
+        Format::Armor => keygen::armor(key_size, &key_bytes).into_bytes(),
+        _ => encode_output(&key_bytes, &output_format),
+    });
+    write_bytes(&encoded, &output_target, true);
+}
 
-    let key_bytes = match matches.value_of("key") {
-        None => {
-            let filename = matches.value_of("key file").unwrap();
-            let key_format = match matches.value_of("key format").unwrap() {
-                "binary" => Format::Binary,
-                "hex" => Format::Hex,
-                _ => unreachable!(),
-            };
+fn mac_command(matches: &ArgMatches) {
+    let input_format = parse_format(matches, "input format");
+    let output_format = parse_format(matches, "output format");
+    let cipher = parse_key_cipher(matches);
+    let input_source = parse_input_source(matches);
+    let output_target = parse_output_target(matches, &input_source);
+
+    let data = read_input(&mut open_input(&input_source), &input_format);
+    write_output(&cmac::cmac(&cipher, &data), &output_format, &output_target);
+}
 
-            read_key_bytes_from_file(filename, &key_format)
+fn verify_command(matches: &ArgMatches) {
+    let input_format = parse_format(matches, "input format");
+    let cipher = parse_key_cipher(matches);
+    let tag = hex::decode(matches.value_of("tag").unwrap()).expect("error converting from hex");
+    let mut file = open_input(&parse_input_source(matches));
+
+    let data = read_input(&mut file, &input_format);
+    if !cmac::verify(&cipher, &data, &tag) {
+        exit_with_error("MAC verification failed");
+    }
+}
+
+fn inspect_command(matches: &ArgMatches) {
+    let input_format = parse_format(matches, "input format");
+    let mut file = open_input(&parse_input_source(matches));
+
+    let header = match input_format {
+        Format::Binary => container::Header::read_from(&mut file),
+        _ => container::Header::read_from(&mut &read_input(&mut file, &input_format)[..]),
+    }.unwrap_or_else(|err| exit_with_error(err));
+
+    println!("version: {}", container::VERSION);
+    println!("key size: {} bits", header.key_size.bits());
+    match header.mode {
+        container::Mode::Eax => println!("mode: eax"),
+    }
+    match header.kdf {
+        container::Kdf::None => println!("kdf: none"),
+        container::Kdf::Pbkdf2 {
+            ref salt,
+            iterations,
+        } => {
+            println!("kdf: pbkdf2");
+            println!("iterations: {}", iterations);
+            println!("salt: {}", hex::encode(salt));
         }
-        Some(key_string) => read_key_bytes_from_string(key_string, &Format::Hex),
+        container::Kdf::Argon2 { ref salt } => {
+            println!("kdf: argon2");
+            println!("salt: {}", hex::encode(salt));
+        }
+    }
+    println!("nonce: {}", hex::encode(header.nonce));
+    println!("chunk size: {}", header.chunk_size);
+    println!("length: {}", header.length);
+}
+
+fn bench_command(matches: &ArgMatches) {
+    let size = matches
+        .value_of("size")
+        .unwrap()
+        .parse::<usize>()
+        .expect("size must be a positive integer");
+    let data = vec![0u8; size - size % present::BLOCK_SIZE_IN_BYTES];
+
+    let ciphers = match matches.value_of("key length").unwrap() {
+        "80" => vec![make_cipher(KeyLength::Key80, &[0u8; 10])],
+        "128" => vec![make_cipher(KeyLength::Key128, &[0u8; 16])],
+        "both" => vec![
+            make_cipher(KeyLength::Key80, &[0u8; 10]),
+            make_cipher(KeyLength::Key128, &[0u8; 16]),
+        ],
+        _ => unreachable!(),
     };
 
-    let input_source = match matches.value_of("FILE") {
-        None => InputSource::Stdin,
-        Some(filename) => match filename {
-            "-" => InputSource::Stdin,
-            _ => InputSource::File(filename.to_string()),
-        },
+    for cipher in ciphers.iter() {
+        let name = format!("PRESENT-{}", cipher.key_size().bits());
+
+        let start = Instant::now();
+        for block in data.chunks(present::BLOCK_SIZE_IN_BYTES) {
+            hint::black_box(cipher.encrypt_block(block));
+        }
+        print_throughput(&name, "ecb", data.len(), start);
+
+        let start = Instant::now();
+        container::encrypt(cipher, cipher.key_size(), container::Kdf::None, &data);
+        print_throughput(&name, "container", data.len(), start);
+    }
+}
+
+fn analyze_command(matches: &ArgMatches) {
+    match matches.subcommand() {
+        ("sbox", Some(matches)) => analyze_sbox_command(matches),
+        ("trail", Some(matches)) => analyze_trail_command(matches),
+        ("experiment", Some(matches)) => analyze_experiment_command(matches),
+        ("integral", Some(matches)) => analyze_integral_command(matches),
+        ("attack", Some(matches)) => analyze_attack_command(matches),
+        _ => unreachable!(),
+    }
+}
+
+fn analyze_sbox_command(matches: &ArgMatches) {
+    let sbox = match matches.value_of("sbox") {
+        Some(sbox) => parse_sbox(sbox),
+        None => present::S,
     };
+    let csv = matches.value_of("format").unwrap() == "csv";
+    let what = matches.value_of("table").unwrap();
 
-    match input_source {
-        InputSource::Stdin => {
-            let stdin = io::stdin();
-            let mut file = stdin.lock();
-
-            if decrypt_mode {
-                decrypt(
-                    &mut file,
-                    key_length,
-                    key_bytes,
-                    &input_format,
-                    &output_format,
-                );
-            } else {
-                encrypt(
-                    &mut file,
-                    key_length,
-                    key_bytes,
-                    &input_format,
-                    &output_format,
-                );
-            }
+    let mut printed_header = false;
+    for &name in ["ddt", "lat", "bct"].iter() {
+        if what != name && what != "all" {
+            continue;
         }
-        InputSource::File(filename) => {
-            let mut file = io::BufReader::new(File::open(filename).expect("file not found"));
-
-            if decrypt_mode {
-                decrypt(
-                    &mut file,
-                    key_length,
-                    key_bytes,
-                    &input_format,
-                    &output_format,
-                );
-            } else {
-                encrypt(
-                    &mut file,
-                    key_length,
-                    key_bytes,
-                    &input_format,
-                    &output_format,
-                );
+        let table = match name {
+            "ddt" => analysis::ddt(&sbox),
+            "lat" => analysis::lat(&sbox),
+            _ => analysis::bct(&sbox),
+        };
+        let table = table.unwrap_or_else(|err| exit_with_error(err));
+        if csv {
+            if !printed_header {
+                let columns: Vec<String> =
+                    (0..analysis::SBOX_SIZE).map(|b| format!("{:x}", b)).collect();
+                println!("table,input,{}", columns.join(","));
+                printed_header = true;
             }
+            print_table_csv(name, &table);
+        } else {
+            print_table(&name.to_uppercase(), &table);
+        }
+    }
+
+    if what == "branch" || what == "all" {
+        let differential =
+            analysis::differential_branch_number(&sbox).unwrap_or_else(|err| exit_with_error(err));
+        let linear =
+            analysis::linear_branch_number(&sbox).unwrap_or_else(|err| exit_with_error(err));
+        if csv {
+            println!("branch,differential,{}", differential);
+            println!("branch,linear,{}", linear);
+        } else {
+            println!("Differential branch number: {}", differential);
+            println!("Linear branch number: {}", linear);
         }
     }
 }
 
-fn encrypt<R: io::BufRead>(
-    file: &mut R,
-    key_length: KeyLength,
-    key_bytes: Vec<u8>,
-    input_format: &Format,
-    output_format: &Format,
-) {
-    match key_length {
-        KeyLength::Key80 => {
-            if key_bytes.len() < present80::KEY_LENGTH_IN_BYTES {
-                eprintln!("warning: provided key contains less than 80 bytes and will be padded with zeroes")
-            } else if key_bytes.len() > present80::KEY_LENGTH_IN_BYTES {
-                eprintln!("warning: provided key contains more than 80 bytes and will be truncated")
-            }
+fn parse_rounds(matches: &ArgMatches) -> usize {
+    let num_rounds = matches
+        .value_of("rounds")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap_or(0);
+    if num_rounds == 0 || num_rounds > present::NUM_ROUNDS {
+        exit_with_error(format!("rounds must be between 1 and {}", present::NUM_ROUNDS));
+    }
+    num_rounds
+}
+
+// Returns the kind along with what to call it and its probability when printing.
+fn parse_trail_kind(matches: &ArgMatches) -> (trail::Kind, &'static str, &'static str) {
+    match matches.value_of("kind").unwrap() {
+        "differential" => (trail::Kind::Differential, "differential", "probability"),
+        "linear" => (trail::Kind::Linear, "linear", "correlation"),
+        _ => unreachable!(),
+    }
+}
+
+fn analyze_trail_command(matches: &ArgMatches) {
+    let num_rounds = parse_rounds(matches);
+    let (kind, what, measure) = parse_trail_kind(matches);
+
+    let trail = trail::best_trail(kind, num_rounds);
+    println!("round  input             output            weight");
+    for (i, round) in trail.rounds.iter().enumerate() {
+        println!("{:<5}  {:016x}  {:016x}  {}", i + 1, round.input, round.output, round.weight);
+    }
+    println!("final  {:016x}", trail.output());
+    println!(
+        "Best {} trail over {} rounds: {} 2^-{}, {} active S-boxes",
+        what,
+        num_rounds,
+        measure,
+        trail.weight(),
+        trail.active_sboxes()
+    );
+}
+
+fn analyze_experiment_command(matches: &ArgMatches) {
+    let num_rounds = parse_rounds(matches);
+    let (kind, what, measure) = parse_trail_kind(matches);
+    let parse_number = |name: &str| {
+        matches.value_of(name).unwrap().parse::<u64>().unwrap_or_else(|_| {
+            exit_with_error(format!("{} must be a positive integer", name))
+        })
+    };
+    let parse_state = |name: &str| {
+        u64::from_str_radix(matches.value_of(name).unwrap(), 16)
+            .unwrap_or_else(|_| exit_with_error(format!("{} must be at most 16 hex digits", name)))
+    };
 
-            let key = present80::Key::new(&key_bytes[..]);
-            present80_encrypt(file, key, input_format, output_format);
+    let (input, output, trail) = if matches.is_present("input") {
+        (parse_state("input"), parse_state("output"), None)
+    } else {
+        let trail = trail::best_trail(kind, num_rounds);
+        (trail.rounds[0].input, trail.output(), Some(trail))
+    };
+
+    let mut experiment = experiment::Experiment::new(kind, num_rounds, input, output);
+    experiment.num_keys = parse_number("keys") as usize;
+    experiment.samples_per_key = parse_number("samples");
+    if experiment.num_keys == 0 || experiment.samples_per_key == 0 {
+        exit_with_error("keys and samples must be positive integers");
+    }
+    if let Some("128") = matches.value_of("key length") {
+        experiment.key_size = container::KeySize::Key128;
+    }
+    if matches.is_present("seed") {
+        experiment.seed = parse_number("seed");
+    }
+
+    let estimate = experiment.run().unwrap_or_else(|err| exit_with_error(err));
+    println!("input   {:016x}", input);
+    println!("output  {:016x}", output);
+    if estimate.probability > 0.0 {
+        println!(
+            "Estimated {} 2^-{:.2} ({:.3e}) from {} samples",
+            measure,
+            estimate.weight(),
+            estimate.probability,
+            estimate.samples
+        );
+    } else {
+        println!("Estimated {} 0 from {} samples", measure, estimate.samples);
+    }
+    if let Some(trail) = trail {
+        println!("Best {} trail predicts {} 2^-{}", what, measure, trail.weight());
+    }
+}
+
+fn analyze_integral_command(matches: &ArgMatches) {
+    let num_rounds = parse_rounds(matches);
+    let active = match matches.values_of("active") {
+        Some(nibbles) => {
+            let nibbles: Vec<usize> = nibbles
+                .map(|nibble| match nibble.trim().parse::<usize>() {
+                    Ok(nibble) if nibble < 16 => nibble,
+                    _ => exit_with_error(format!("{} is not a nibble between 0 and 15", nibble)),
+                })
+                .collect();
+            integral::nibble_mask(&nibbles)
         }
-        KeyLength::Key128 => {
-            if key_bytes.len() < present128::KEY_LENGTH_IN_BYTES {
-                eprintln!("warning: provided key contains less than 128 bytes and will be padded with zeroes");
-            } else if key_bytes.len() > present128::KEY_LENGTH_IN_BYTES {
-                eprintln!(
-                    "warning: provided key contains more than 128 bytes and will be truncated"
-                );
+        None => u64::from_str_radix(matches.value_of("cube").unwrap(), 16)
+            .unwrap_or_else(|_| exit_with_error("cube must be at most 16 hex digits")),
+    };
+    if active.count_ones() > 32 {
+        exit_with_error("a structure of more than 2^32 plaintexts would take too long");
+    }
+
+    let mut integral = integral::Integral::new(num_rounds, active);
+    integral.trials = matches
+        .value_of("trials")
+        .unwrap()
+        .parse::<usize>()
+        .ok()
+        .filter(|&trials| trials > 0)
+        .unwrap_or_else(|| exit_with_error("trials must be a positive integer"));
+    if let Some("128") = matches.value_of("key length") {
+        integral.key_size = container::KeySize::Key128;
+    }
+    if let Some(seed) = matches.value_of("seed") {
+        integral.seed = seed
+            .parse::<u64>()
+            .unwrap_or_else(|_| exit_with_error("seed must be a positive integer"));
+    }
+
+    let balanced = integral.run().unwrap_or_else(|err| exit_with_error(err));
+    // One character per output bit, most significant first: B if balanced, ? if not.
+    let bits: Vec<String> = (0..16)
+        .rev()
+        .map(|nibble| {
+            (0..4)
+                .rev()
+                .map(|bit| match (balanced >> (4 * nibble + bit)) & 1 {
+                    1 => 'B',
+                    _ => '?',
+                })
+                .collect()
+        })
+        .collect();
+    println!("active    {:016x}", active);
+    println!("balanced  {:016x}", balanced);
+    println!("bits      {}", bits.join(" "));
+    println!(
+        "{} of 64 output bits balanced after {} rounds over {} trials of 2^{} plaintexts",
+        balanced.count_ones(),
+        num_rounds,
+        integral.trials,
+        active.count_ones()
+    );
+}
+
+fn analyze_attack_command(matches: &ArgMatches) {
+    let num_rounds = parse_rounds(matches);
+    let key = match matches.value_of("key") {
+        Some(key) => {
+            let bytes = Secret(hex::decode(key).unwrap_or_default());
+            if bytes.len() != present80::KEY_LENGTH_IN_BYTES {
+                exit_with_error("key must be 20 hex digits");
             }
+            present80::Key::new(&bytes)
+        }
+        None => keygen::generate_key80().unwrap_or_else(|err| exit_with_error(err)),
+    };
 
-            let key = present128::Key::new(&key_bytes[..]);
-            unimplemented!();
+    let mut attack = attack::Attack::new(num_rounds);
+    attack.right_pairs = matches
+        .value_of("pairs")
+        .unwrap()
+        .parse::<u64>()
+        .ok()
+        .filter(|&pairs| pairs > 0)
+        .unwrap_or_else(|| exit_with_error("pairs must be a positive integer"));
+    if let Some(seed) = matches.value_of("seed") {
+        attack.seed = seed
+            .parse::<u64>()
+            .unwrap_or_else(|_| exit_with_error("seed must be a positive integer"));
+    }
+
+    let secret = hex::encode(key.as_bytes());
+    let oracle = reduced::Cipher::new80(key, reduced::Rounds::new(num_rounds))
+        .unwrap_or_else(|err| exit_with_error(err));
+    let recovery = attack
+        .recover_key(&oracle)
+        .unwrap_or_else(|err| exit_with_error(err));
+
+    println!("trail  input             output            weight");
+    for (i, trail) in recovery.trails.iter().enumerate() {
+        println!(
+            "{:<5}  {:016x}  {:016x}  {}",
+            i + 1,
+            trail.rounds[0].input,
+            trail.output(),
+            trail.weight()
+        );
+    }
+    // The votes are for the whitening key before the permutation, nibble by nibble.
+    println!("nibble  guess  votes  next best");
+    for (i, votes) in recovery.votes.iter().enumerate() {
+        let mut sorted: Vec<(u64, usize)> =
+            votes.iter().enumerate().map(|(guess, &count)| (count, guess)).collect();
+        sorted.sort_by(|a, b| b.cmp(a));
+        println!("{:<6}  {:<5x}  {:<5}  {}", i, sorted[0].1, sorted[0].0, sorted[1].0);
+    }
+    println!("last round key  {:016x}", recovery.last_round_key);
+    println!("recovered key   {}", hex::encode(recovery.key.as_bytes()));
+    println!("secret key      {}", secret);
+    println!(
+        "Recovered the key of {} rounds with {} chosen plaintexts and {} trails",
+        num_rounds,
+        recovery.chosen_plaintexts,
+        recovery.trails.len()
+    );
+}
+
+fn parse_sbox(s: &str) -> [u8; analysis::SBOX_SIZE] {
+    let digits: Vec<u8> = s
+        .chars()
+        .map(|c| {
+            c.to_digit(16)
+                .unwrap_or_else(|| exit_with_error(format!("invalid hex digit {:?} in S-box", c)))
+                as u8
+        })
+        .collect();
+    if digits.len() != analysis::SBOX_SIZE {
+        exit_with_error(format!("S-box must have {} entries", analysis::SBOX_SIZE));
+    }
+
+    let mut sbox = [0u8; analysis::SBOX_SIZE];
+    sbox.copy_from_slice(&digits);
+    sbox
+}
+
+fn print_table(name: &str, table: &analysis::Table) {
+    println!("{}", name);
+    print!("   ");
+    for b in 0..analysis::SBOX_SIZE {
+        print!("{:>4x}", b);
+    }
+    println!();
+    for (a, row) in table.iter().enumerate() {
+        print!("{:>3x}", a);
+        for entry in row.iter() {
+            print!("{:>4}", entry);
         }
-        KeyLength::Auto => if key_bytes.len() <= 80 {
-            encrypt(
-                file,
-                KeyLength::Key80,
-                key_bytes,
-                input_format,
-                output_format,
-            );
+        println!();
+    }
+    println!();
+}
+
+fn print_table_csv(name: &str, table: &analysis::Table) {
+    for (a, row) in table.iter().enumerate() {
+        let entries: Vec<String> = row.iter().map(|entry| entry.to_string()).collect();
+        println!("{},{:x},{}", name, a, entries.join(","));
+    }
+}
+
+fn print_throughput(name: &str, what: &str, size: usize, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64();
+    let throughput = size as f64 / seconds / (1 << 20) as f64;
+    println!("{} {}: {} bytes in {:.3}s ({:.2} MiB/s)", name, what, size, seconds, throughput);
+}
+
+fn print_stats(size: u64, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64();
+    let blocks = size.div_ceil(present::BLOCK_SIZE_IN_BYTES as u64);
+    let throughput = size as f64 / seconds / (1 << 20) as f64;
+    eprintln!("{} blocks in {:.3}s ({:.2} MiB/s)", blocks, seconds, throughput);
+}
+
+// The progress line is redrawn in place, so it is only shown when standard error is a terminal.
+fn parse_progress(matches: &ArgMatches, total: u64) -> Option<Progress> {
+    if matches.is_present("progress") && io::stderr().is_terminal() {
+        Some(Progress::new(total))
+    } else {
+        None
+    }
+}
+
+impl Progress {
+    fn new(total: u64) -> Progress {
+        Progress {
+            total,
+            start: Instant::now(),
+            last_update: None,
+        }
+    }
+
+    fn update(&mut self, processed: u64) {
+        let now = Instant::now();
+        let interval = Duration::from_millis(PROGRESS_INTERVAL_IN_MILLIS);
+        match self.last_update {
+            Some(last_update) if processed < self.total && now - last_update < interval => return,
+            _ => self.last_update = Some(now),
+        }
+
+        let seconds = (now - self.start).as_secs_f64();
+        let throughput = if seconds > 0.0 { processed as f64 / seconds } else { 0.0 };
+        let eta = if throughput > 0.0 {
+            format!("{:.0}s", (self.total - processed) as f64 / throughput)
         } else {
-            encrypt(
-                file,
-                KeyLength::Key128,
-                key_bytes,
-                input_format,
-                output_format,
-            );
-        },
+            "-".to_string()
+        };
+        eprint!(
+            "\r{} / {} bytes, {:.2} MiB/s, ETA {}    ",
+            processed,
+            self.total,
+            throughput / (1 << 20) as f64,
+            eta
+        );
+        if processed == self.total {
+            eprintln!();
+        }
     }
 }
 
-fn decrypt<R: io::BufRead>(
-    file: &mut R,
-    key_length: KeyLength,
-    key_bytes: Vec<u8>,
-    input_format: &Format,
-    output_format: &Format,
-) {
+impl Cipher {
+    fn key_size(&self) -> container::KeySize {
+        match *self {
+            Cipher::Present80(_) => container::KeySize::Key80,
+            Cipher::Present128(_) => container::KeySize::Key128,
+        }
+    }
+
+    fn encrypt_traced(&self, state: u64) -> trace::Trace {
+        match *self {
+            Cipher::Present80(ref cipher) => cipher.encrypt_traced(state),
+            Cipher::Present128(ref cipher) => cipher.encrypt_traced(state),
+        }
+    }
+}
+
+// Tables are separated by blank lines, and JSON traces are written one per line.
+fn print_traces(cipher: &Cipher, data: &[u8], trace_format: &str) {
+    for (i, block) in data.chunks(present::BLOCK_SIZE_IN_BYTES).enumerate() {
+        let trace = cipher.encrypt_traced(present::bytes_to_state(block));
+        match trace_format {
+            "json" => eprintln!("{}", trace.to_json()),
+            _ if i == 0 => eprint!("{}", trace.to_table()),
+            _ => eprint!("\n{}", trace.to_table()),
+        }
+    }
+}
+
+impl BlockCipher for Cipher {
+    fn encrypt_state(&self, state: u64) -> u64 {
+        match *self {
+            Cipher::Present80(ref cipher) => cipher.encrypt_state(state),
+            Cipher::Present128(ref cipher) => cipher.encrypt_state(state),
+        }
+    }
+
+    fn decrypt_state(&self, state: u64) -> u64 {
+        match *self {
+            Cipher::Present80(ref cipher) => cipher.decrypt_state(state),
+            Cipher::Present128(ref cipher) => cipher.decrypt_state(state),
+        }
+    }
+}
+
+fn exit_with_error<E: fmt::Display>(err: E) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(1);
+}
+
+fn make_cipher(key_length: KeyLength, key_bytes: &[u8]) -> Cipher {
     match key_length {
         KeyLength::Key80 => {
-            if key_bytes.len() < present80::KEY_LENGTH_IN_BYTES {
-                eprintln!("warning: provided key contains less than 80 bytes and will be padded with zeroes")
-            } else if key_bytes.len() > present80::KEY_LENGTH_IN_BYTES {
-                eprintln!("warning: provided key contains more than 80 bytes and will be truncated")
-            }
-
-            let key = present80::Key::new(&key_bytes[..]);
-            present80_decrypt(file, key, input_format, output_format);
+            warn_key_length(key_bytes, present80::KEY_LENGTH_IN_BYTES);
+            Cipher::Present80(present80::Cipher::new(present80::Key::new(key_bytes)))
         }
         KeyLength::Key128 => {
-            if key_bytes.len() < present128::KEY_LENGTH_IN_BYTES {
-                eprintln!("warning: provided key contains less than 128 bytes and will be padded with zeroes");
-            } else if key_bytes.len() > present128::KEY_LENGTH_IN_BYTES {
-                eprintln!(
-                    "warning: provided key contains more than 128 bytes and will be truncated"
-                );
-            }
-
-            let key = present128::Key::new(&key_bytes[..]);
-            unimplemented!();
+            warn_key_length(key_bytes, present128::KEY_LENGTH_IN_BYTES);
+            Cipher::Present128(present128::Cipher::new(present128::Key::new(key_bytes)))
         }
-        KeyLength::Auto => if key_bytes.len() <= 80 {
-            decrypt(
-                file,
-                KeyLength::Key80,
-                key_bytes,
-                input_format,
-                output_format,
-            );
-        } else {
-            decrypt(
-                file,
-                KeyLength::Key128,
-                key_bytes,
-                input_format,
-                output_format,
-            );
+        KeyLength::Auto => match key_bytes.len() {
+            present80::KEY_LENGTH_IN_BYTES => make_cipher(KeyLength::Key80, key_bytes),
+            present128::KEY_LENGTH_IN_BYTES => make_cipher(KeyLength::Key128, key_bytes),
+            len => exit_with_error(format!(
+                "provided key is {} bits long; use an 80-bit or 128-bit key, or pass --key-length",
+                len * 8
+            )),
         },
     }
 }
 
-fn read_key_bytes_from_file(filename: &str, format: &Format) -> Vec<u8> {
-    let mut file = File::open(filename).expect("file not found!");
-    match format {
-        &Format::Binary => {
-            let mut buf = [0u8; MAX_KEY_LENGTH_BINARY];
-            let bytes_read = file.read(&mut buf[..]).expect("error reading file");
+fn warn_key_length(key_bytes: &[u8], key_length_in_bytes: usize) {
+    let bits = key_bytes.len() * 8;
+    let expected_bits = key_length_in_bytes * 8;
 
-            let mut key_bytes: Vec<u8> = Vec::with_capacity(bytes_read);
-            key_bytes.extend_from_slice(&buf[..bytes_read]);
+    if bits < expected_bits {
+        eprintln!(
+            "warning: provided key is {} bits long and will be padded with zeroes to {} bits",
+            bits, expected_bits
+        );
+    } else if bits > expected_bits {
+        eprintln!(
+            "warning: provided key is {} bits long and will be truncated to {} bits",
+            bits, expected_bits
+        );
+    }
+}
+
+// Passwords derive 128-bit keys unless told otherwise.
+fn password_key_length(key_length: KeyLength) -> KeyLength {
+    match key_length {
+        KeyLength::Auto => KeyLength::Key128,
+        key_length => key_length,
+    }
+}
 
-            key_bytes
+fn raw_key(key_length: KeyLength, key_source: KeySource) -> (KeyLength, Secret) {
+    match key_source {
+        KeySource::Bytes(key_bytes) => (key_length, key_bytes),
+        KeySource::Password {
+            password,
+            salt,
+            kdf,
+            iterations,
+        } => {
+            let salt =
+                salt.unwrap_or_else(|| exit_with_error("--salt is required outside eax mode"));
+            let key_length = password_key_length(key_length);
+            let key_bytes = derive_key_bytes(&password, &salt, &kdf, iterations, key_length);
+            (key_length, key_bytes)
         }
-        &Format::Hex => {
-            let mut buf = [0u8; MAX_KEY_LENGTH_HEX];
-            let bytes_read = file.read(&mut buf[..]).expect("error reading file");
+    }
+}
 
-            let key_bytes = hex::decode(&buf[..bytes_read]).expect("error converting from hex");
+fn make_raw_cipher(key_length: KeyLength, key_source: KeySource) -> Cipher {
+    let (key_length, key_bytes) = raw_key(key_length, key_source);
+    make_cipher(key_length, &key_bytes)
+}
+
+// The MANIFEST is authenticated under its own key, so none of its tags is a CMAC under the key
+// that encrypts the files. That key already has full entropy, so one iteration is enough.
+fn manifest_cipher(cipher: &Cipher, key_bytes: &[u8]) -> Cipher {
+    let key_length = match cipher.key_size() {
+        container::KeySize::Key80 => KeyLength::Key80,
+        container::KeySize::Key128 => KeyLength::Key128,
+    };
+    let mac_key_bytes =
+        derive_key_bytes(key_bytes, MANIFEST_KDF_LABEL, &Kdf::Pbkdf2, 1, key_length);
+    make_cipher(key_length, &mac_key_bytes)
+}
 
-            key_bytes
+// Without an explicit IV, a random one is generated and written before the ciphertext.
+fn encrypt_raw<C: BlockCipher, R: Read>(
+    cipher: &C,
+    mode: Mode,
+    iv: Option<Vec<u8>>,
+    padding: modes::Padding,
+    input: &mut R,
+    progress: Option<Progress>,
+) -> Result<Vec<u8>, String> {
+    let mut output: Vec<u8> = Vec::new();
+    let iv = match (mode, iv) {
+        (Mode::Ecb, _) => Vec::new(),
+        (_, Some(iv)) => iv,
+        (_, None) => {
+            let mut iv = vec![0u8; modes::IV_SIZE_IN_BYTES];
+            getrandom::getrandom(&mut iv).map_err(|err| err.to_string())?;
+            output.extend_from_slice(&iv);
+            iv
         }
-    }
+    };
+
+    let mut stream = RawStream::new(cipher, mode, false, iv, progress);
+    let mut rest = Vec::new();
+    stream.read_blocks(input, &mut rest, &mut output)?;
+    let mut last = match mode {
+        Mode::Ecb | Mode::Cbc => modes::pad(padding, &rest).map_err(|err| err.to_string())?,
+        _ => rest,
+    };
+    stream.apply(&mut last).map_err(|err| err.to_string())?;
+
+    output.extend_from_slice(&last);
+    Ok(output)
 }
 
-fn read_key_bytes_from_string(s: &str, format: &Format) -> Vec<u8> {
-    match format {
-        &Format::Binary => {
-            let mut key_bytes: Vec<u8> = Vec::with_capacity(s.len());
-            key_bytes.extend_from_slice(s.as_bytes());
+fn decrypt_raw<C: BlockCipher, R: Read>(
+    cipher: &C,
+    mode: Mode,
+    iv: Option<Vec<u8>>,
+    padding: modes::Padding,
+    input: &mut R,
+    progress: Option<Progress>,
+) -> Vec<u8> {
+    let mut stream = RawStream::new(cipher, mode, true, Vec::new(), progress);
+    stream.iv = match (mode, iv) {
+        (Mode::Ecb, _) => Vec::new(),
+        (_, Some(iv)) => iv,
+        (_, None) => {
+            let mut iv = vec![0u8; modes::IV_SIZE_IN_BYTES];
+            input.read_exact(&mut iv).unwrap_or_else(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => {
+                    exit_with_error("input is too short to contain an IV")
+                }
+                _ => exit_with_error(err),
+            });
+            stream.processed = iv.len() as u64;
+            iv
+        }
+    };
+
+    let mut output = Vec::new();
+    let mut rest = Vec::new();
+    stream
+        .read_blocks(input, &mut rest, &mut output)
+        .unwrap_or_else(|err| exit_with_error(err));
+    stream.apply(&mut rest).unwrap_or_else(|err| exit_with_error(err));
+    output.extend_from_slice(&rest);
 
-            key_bytes
+    match mode {
+        Mode::Ecb | Mode::Cbc => {
+            modes::unpad(padding, output).unwrap_or_else(|err| exit_with_error(err))
         }
-        &Format::Hex => hex::decode(s).expect("error converting from hex"),
+        _ => output,
     }
 }
 
-fn read_block_from_file<R: io::BufRead>(file: &mut R, input_format: &Format) -> Option<Vec<u8>> {
-    match input_format {
-        &Format::Binary => {
-            let mut buf = [0u8; BLOCK_SIZE_BINARY];
-            let bytes_read = file.read(&mut buf[..]).expect("error reading file");
+impl<'a, C: BlockCipher> RawStream<'a, C> {
+    fn new(
+        cipher: &'a C,
+        mode: Mode,
+        decrypt: bool,
+        iv: Vec<u8>,
+        progress: Option<Progress>,
+    ) -> RawStream<'a, C> {
+        RawStream {
+            cipher,
+            mode,
+            decrypt,
+            iv,
+            processed: 0,
+            progress,
+        }
+    }
 
-            if bytes_read == 0 {
-                return None;
+    // Appends the result for every whole block read to output and leaves the rest in rest.
+    fn read_blocks<R: Read>(
+        &mut self,
+        input: &mut R,
+        rest: &mut Vec<u8>,
+        output: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        loop {
+            let n = input
+                .by_ref()
+                .take(RAW_CHUNK_SIZE_IN_BYTES)
+                .read_to_end(rest)
+                .map_err(|err| err.to_string())?;
+            if n == 0 {
+                return Ok(());
             }
 
-            let mut block: Vec<u8> = Vec::with_capacity(bytes_read);
-            block.extend_from_slice(&buf[..bytes_read]);
+            let whole_blocks = rest.len() - rest.len() % present::BLOCK_SIZE_IN_BYTES;
+            let mut chunk: Vec<u8> = rest.drain(..whole_blocks).collect();
+            self.apply(&mut chunk).map_err(|err| err.to_string())?;
+            output.extend_from_slice(&chunk);
+
+            self.processed += n as u64;
+            if let Some(ref mut progress) = self.progress {
+                progress.update(self.processed);
+            }
+        }
+    }
 
-            Some(block)
+    fn apply(&mut self, data: &mut [u8]) -> Result<(), modes::Error> {
+        let last_block = data.len().saturating_sub(present::BLOCK_SIZE_IN_BYTES);
+        let input_block = data[last_block..].to_vec();
+        let (cipher, iv) = (self.cipher, &self.iv[..]);
+        match (self.mode, self.decrypt) {
+            (Mode::Eax, _) => unreachable!(),
+            (Mode::Ecb, false) => modes::ecb_encrypt(cipher, data),
+            (Mode::Ecb, true) => modes::ecb_decrypt(cipher, data),
+            (Mode::Cbc, false) => modes::cbc_encrypt(cipher, iv, data),
+            (Mode::Cbc, true) => modes::cbc_decrypt(cipher, iv, data),
+            (Mode::Ctr, _) => modes::ctr(cipher, iv, data),
+            (Mode::Cfb, false) => modes::cfb_encrypt(cipher, iv, data),
+            (Mode::Cfb, true) => modes::cfb_decrypt(cipher, iv, data),
+            (Mode::Ofb, _) => modes::ofb(cipher, iv, data),
+        }?;
+        if data.is_empty() {
+            return Ok(());
         }
-        &Format::Hex => {
-            let mut buf = [0u8; BLOCK_SIZE_HEX];
-            let bytes_read = file.read(&mut buf[..]).expect("error reading file");
 
-            if bytes_read == 0 {
-                return None;
+        // The next chunk starts from the state the mode would have reached after this one.
+        let output_block = &data[last_block..];
+        self.iv = match (self.mode, self.decrypt) {
+            (Mode::Eax, _) | (Mode::Ecb, _) => return Ok(()),
+            (Mode::Cbc, false) | (Mode::Cfb, false) => output_block.to_vec(),
+            (Mode::Cbc, true) | (Mode::Cfb, true) => input_block,
+            (Mode::Ctr, _) => {
+                let blocks = (data.len() / present::BLOCK_SIZE_IN_BYTES) as u64;
+                let counter = present::bytes_to_state(&self.iv).wrapping_add(blocks);
+                present::state_to_bytes(counter).to_vec()
             }
+            (Mode::Ofb, _) => input_block
+                .iter()
+                .zip(output_block.iter())
+                .map(|(x, y)| x ^ y)
+                .collect(),
+        };
+        Ok(())
+    }
+}
 
-            let block = hex::decode(&buf[..bytes_read]).expect("error converting from hex");
+// The key is derived once and its parameters recorded in every container header.
+fn container_key(
+    key_length: KeyLength,
+    key_source: KeySource,
+) -> (KeyLength, Secret, container::Kdf) {
+    match key_source {
+        KeySource::Bytes(key_bytes) => (key_length, key_bytes, container::Kdf::None),
+        KeySource::Password {
+            password,
+            salt,
+            kdf,
+            iterations,
+        } => {
+            let key_length = password_key_length(key_length);
+            let salt = salt.unwrap_or_else(container::random_salt);
+            let key_bytes = derive_key_bytes(&password, &salt, &kdf, iterations, key_length);
+            let kdf = match kdf {
+                Kdf::Pbkdf2 => container::Kdf::Pbkdf2 { salt, iterations },
+                Kdf::Argon2 => container::Kdf::Argon2 { salt },
+            };
 
-            Some(block)
+            (key_length, key_bytes, kdf)
         }
     }
 }
 
-fn present80_encrypt<R: io::BufRead>(
-    file: &mut R,
-    key: present80::Key,
-    input_format: &Format,
-    output_format: &Format,
-) {
-    let stdout = io::stdout();
-    let mut out = io::BufWriter::new(stdout.lock());
+fn encrypt_container<R: Read>(
+    cipher: &Cipher,
+    kdf: container::Kdf,
+    input: &mut R,
+    size: u64,
+    progress: Option<Progress>,
+) -> Vec<u8> {
+    let header = container::Header::new(cipher.key_size(), kdf, size);
+    let mut writer = container::Writer::new(Vec::new(), cipher, header)
+        .unwrap_or_else(|err| exit_with_error(err));
+    if let Some(mut progress) = progress {
+        writer.set_progress(move |processed| progress.update(processed));
+    }
 
-    loop {
-        let block = match read_block_from_file(file, input_format) {
-            None => return,
-            Some(block) => block,
-        };
-        let encrypted = present80::encrypt_block(&block[..], key);
-        match output_format {
-            &Format::Binary => out.write_all(&encrypted[..]),
-            &Format::Hex => out.write_all(hex::encode(&encrypted[..]).as_bytes()),
-        }.expect("error writing to stdout");
+    io::copy(input, &mut writer).unwrap_or_else(|err| exit_with_error(err));
+    writer.finish().unwrap_or_else(|err| exit_with_error(err))
+}
+
+fn decrypt_container<R: Read>(
+    input: &mut R,
+    key_length: KeyLength,
+    key_source: KeySource,
+    matches: &ArgMatches,
+) -> Vec<u8> {
+    let header = container::Header::read_from(input).unwrap_or_else(|err| exit_with_error(err));
+
+    let header_key_length = match (key_length, header.key_size) {
+        (KeyLength::Key128, container::KeySize::Key80)
+        | (KeyLength::Key80, container::KeySize::Key128) => exit_with_error(format!(
+            "input was encrypted with a {}-bit key",
+            header.key_size.bits()
+        )),
+        (_, container::KeySize::Key80) => KeyLength::Key80,
+        (_, container::KeySize::Key128) => KeyLength::Key128,
+    };
+
+    let key_bytes = match key_source {
+        KeySource::Bytes(key_bytes) => key_bytes,
+        KeySource::Password { password, .. } => match header.kdf {
+            container::Kdf::None => exit_with_error("input was not encrypted with a password"),
+            container::Kdf::Pbkdf2 {
+                ref salt,
+                iterations,
+            } => derive_key_bytes(&password, salt, &Kdf::Pbkdf2, iterations, header_key_length),
+            container::Kdf::Argon2 { ref salt } => {
+                derive_key_bytes(&password, salt, &Kdf::Argon2, 0, header_key_length)
+            }
+        },
+    };
+
+    let cipher = make_cipher(header_key_length, &key_bytes);
+    let progress = parse_progress(matches, header.length);
+    let mut reader = container::Reader::new(input, &cipher, header);
+    if let Some(mut progress) = progress {
+        reader.set_progress(move |processed| progress.update(processed));
     }
+
+    let mut decrypted = Vec::new();
+    io::copy(&mut reader, &mut decrypted).unwrap_or_else(|err| exit_with_error(err));
+    decrypted
 }
 
-fn present80_decrypt<R: io::BufRead>(
-    file: &mut R,
-    key: present80::Key,
+fn read_input<R: io::BufRead>(file: &mut R, input_format: &Format) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    file.read_to_end(&mut data).expect("error reading file");
+
+    decode_input(data, input_format)
+}
+
+// Text formats may be wrapped over several lines or have a trailing newline.
+fn decode_input(data: Vec<u8>, input_format: &Format) -> Vec<u8> {
+    try_decode_input(data, input_format).unwrap_or_else(|err| exit_with_error(err))
+}
+
+fn try_decode_input(data: Vec<u8>, input_format: &Format) -> Result<Vec<u8>, String> {
+    if let Format::Binary = *input_format {
+        return Ok(data);
+    }
+
+    // The text may be an encoded key, so it is wiped once decoded.
+    let text = String::from_utf8(data).map_err(|_| "input is not text".to_string())?;
+    let result = match *input_format {
+        Format::Binary => unreachable!(),
+        Format::Hex => {
+            let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+            let result = hex::decode(&digits).map_err(|err| err.to_string());
+            present::zeroize(&mut digits.into_bytes());
+            result
+        }
+        Format::Base64 => encoding::base64_decode(&text).map_err(|err| err.to_string()),
+        Format::Base64Url => encoding::base64_url_decode(&text).map_err(|err| err.to_string()),
+        Format::Base32 => encoding::base32_decode(&text).map_err(|err| err.to_string()),
+        Format::Armor => encoding::dearmor(&text)
+            .map(|(_, data)| data)
+            .map_err(|err| err.to_string()),
+    };
+    present::zeroize(&mut text.into_bytes());
+
+    result.map_err(|err| format!("error decoding input: {}", err))
+}
+
+fn encode_output(data: &[u8], output_format: &Format) -> Vec<u8> {
+    match *output_format {
+        Format::Binary => data.to_vec(),
+        Format::Hex => hex::encode(data).into_bytes(),
+        Format::Base64 => encoding::base64_encode(data).into_bytes(),
+        Format::Base64Url => encoding::base64_url_encode(data).into_bytes(),
+        Format::Base32 => encoding::base32_encode(data).into_bytes(),
+        Format::Armor => encoding::armor(ARMOR_LABEL, data).into_bytes(),
+    }
+}
+
+fn write_output(data: &[u8], output_format: &Format, output_target: &OutputTarget) {
+    write_bytes(&encode_output(data, output_format), output_target, false);
+}
+
+// Private files are only readable and writable by their owner.
+fn write_bytes(encoded: &[u8], output_target: &OutputTarget, private: bool) {
+    match *output_target {
+        OutputTarget::Stdout => {
+            let stdout = io::stdout();
+            let mut out = io::BufWriter::new(stdout.lock());
+            out.write_all(encoded).expect("error writing to stdout");
+        }
+        OutputTarget::File {
+            ref path,
+            force,
+            ref permissions,
+        } => {
+            let permissions = if private {
+                private_permissions()
+            } else {
+                permissions.clone()
+            };
+            let permissions = permissions.as_ref();
+            if let Err(err) = write_file_atomically(path, encoded, force, permissions, None) {
+                exit_with_error(format!("error writing {}: {}", path.display(), err));
+            }
+        }
+    }
+}
+
+// Symbolic links are not followed, so a link cannot pull files from outside the tree in.
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(root, &entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path().strip_prefix(root).unwrap().to_path_buf());
+        } else {
+            eprintln!(
+                "warning: skipping {}, which is not a regular file or directory",
+                entry.path().display()
+            );
+        }
+    }
+    Ok(())
+}
+
+// Every file is attempted before any error is reported, so no thread exits while another is
+// still writing a temporary file.
+fn encrypt_directory<F: Fn(Vec<u8>) -> Result<Vec<u8>, String> + Sync>(
+    input_dir: &Path,
+    out_dir: &Path,
+    mac_cipher: &Cipher,
+    encrypt: &F,
     input_format: &Format,
     output_format: &Format,
+    force: bool,
 ) {
-    let stdout = io::stdout();
-    let mut out = io::BufWriter::new(stdout.lock());
+    let mut files: Vec<PathBuf> = Vec::new();
+    if let Err(err) = list_files(input_dir, input_dir, &mut files) {
+        exit_with_error(format!("error reading {}: {}", input_dir.display(), err));
+    }
+    files.sort();
 
-    loop {
-        let block = match read_block_from_file(file, input_format) {
-            None => return,
-            Some(block) => block,
-        };
-        let decrypted = present80::decrypt_block(&block[..], key);
-        match output_format {
-            &Format::Binary => out.write_all(&decrypted[..]),
-            &Format::Hex => out.write_all(hex::encode(&decrypted[..]).as_bytes()),
-        }.expect("error writing to stdout");
+    if files.iter().any(|path| path == Path::new(MANIFEST_FILE_NAME)) {
+        exit_with_error(format!("{} would overwrite an encrypted file", MANIFEST_FILE_NAME));
+    }
+    if !force {
+        let manifest = Path::new(MANIFEST_FILE_NAME);
+        for path in files.iter().map(|path| path.as_path()).chain(Some(manifest)) {
+            if out_dir.join(path).exists() {
+                exit_with_error(format!(
+                    "{} already exists, pass --force to overwrite it",
+                    out_dir.join(path).display()
+                ));
+            }
+        }
+    }
+
+    let results: Vec<Result<[u8; cmac::MAC_SIZE_IN_BYTES], String>> = files
+        .par_iter()
+        .map(|path| {
+            let source = input_dir.join(path);
+            let destination = out_dir.join(path);
+
+            let data = fs::read(&source)
+                .map_err(|err| err.to_string())
+                .and_then(|data| try_decode_input(data, input_format))
+                .map_err(|err| format!("error reading {}: {}", source.display(), err))?;
+            let encrypted = encrypt(data)
+                .map_err(|err| format!("error encrypting {}: {}", source.display(), err))?;
+            let output = encode_output(&encrypted, output_format);
+            write_file_with_mtime(&destination, &output, &source, force)
+                .map_err(|err| format!("error writing {}: {}", destination.display(), err))?;
+
+            Ok(cmac::cmac(mac_cipher, &output))
+        })
+        .collect();
+
+    let mut manifest = String::new();
+    let mut failed = false;
+    for (path, result) in files.iter().zip(results.iter()) {
+        match *result {
+            Ok(ref mac) => {
+                manifest.push_str(&format!("{}  {}\n", hex::encode(mac), path.display()))
+            }
+            Err(ref err) => {
+                eprintln!("error: {}", err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        exit_with_error("some files could not be encrypted, so no MANIFEST was written");
+    }
+    let manifest_path = out_dir.join(MANIFEST_FILE_NAME);
+    let manifest = manifest.as_bytes();
+    if let Err(err) = write_file_atomically(&manifest_path, manifest, force, None, None) {
+        exit_with_error(format!("error writing {}: {}", manifest_path.display(), err));
+    }
+}
+
+// Creates any missing parent directories and gives the new file the same permissions and mtime
+// as source.
+fn write_file_with_mtime(
+    destination: &Path,
+    data: &[u8],
+    source: &Path,
+    force: bool,
+) -> io::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let metadata = fs::metadata(source)?;
+    let permissions = metadata.permissions();
+    write_file_atomically(destination, data, force, Some(&permissions), Some(metadata.modified()?))
+}
+
+#[cfg(unix)]
+fn private_permissions() -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn private_permissions() -> Option<fs::Permissions> {
+    None
+}
+
+// The data goes to a temporary file in the same directory, which is renamed over the
+// destination once it has been written in full, so readers never see partial output. Without
+// force the temporary file is linked to the destination instead, which fails if it exists.
+// The mtime is set through the handle the data was written with, which works even when the
+// permissions make the file read-only.
+fn write_file_atomically(
+    path: &Path,
+    data: &[u8],
+    force: bool,
+    permissions: Option<&fs::Permissions>,
+    modified: Option<SystemTime>,
+) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // Nobody else can read the data before the permissions are set.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(if permissions.is_some() { 0o600 } else { 0o666 });
     }
+
+    let result = options
+        .open(&temp_path)
+        .and_then(|mut file| {
+            if let Some(permissions) = permissions {
+                file.set_permissions(permissions.clone())?;
+            }
+            file.write_all(data)?;
+            if let Some(modified) = modified {
+                file.set_modified(modified)?;
+            }
+            file.sync_all()
+        })
+        .and_then(|_| {
+            if force {
+                return fs::rename(&temp_path, path);
+            }
+            fs::hard_link(&temp_path, path).map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "file exists, pass --force to overwrite it",
+                ),
+                _ => err,
+            })
+        });
+
+    if result.is_err() || !force {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn read_key_bytes_from_file(filename: &str, format: &Format) -> Secret {
+    let mut file = File::open(filename).expect("file not found!");
+    read_key_bytes(&mut file, format)
+}
+
+#[cfg(unix)]
+fn read_key_bytes_from_fd(fd: i32, format: &Format) -> Secret {
+    use std::os::fd::BorrowedFd;
+
+    if fd < 0 {
+        exit_with_error("file descriptor must be a non-negative integer");
+    }
+    if fd == 1 || fd == 2 {
+        exit_with_error("cannot read the key from standard output or standard error");
+    }
+    // The descriptor still belongs to the caller, so only a duplicate is read and closed. If fd
+    // is not open, duplicating it fails with EBADF before anything is read.
+    let owned = unsafe { BorrowedFd::borrow_raw(fd) }
+        .try_clone_to_owned()
+        .unwrap_or_else(|err| {
+            exit_with_error(format!("cannot read file descriptor {}: {}", fd, err))
+        });
+    read_key_bytes(&mut File::from(owned), format)
+}
+
+#[cfg(not(unix))]
+fn read_key_bytes_from_fd(_fd: i32, _format: &Format) -> Secret {
+    exit_with_error("--key-fd is only supported on Unix")
+}
+
+// Text formats may have trailing newlines, which are ignored along with other whitespace.
+fn read_key_bytes<R: Read>(file: &mut R, format: &Format) -> Secret {
+    match *format {
+        Format::Binary => {
+            let mut key_bytes: Vec<u8> = Vec::with_capacity(MAX_KEY_LENGTH_BINARY);
+            file.take(MAX_KEY_LENGTH_BINARY as u64)
+                .read_to_end(&mut key_bytes)
+                .expect("error reading file");
+
+            Secret(key_bytes)
+        }
+        Format::Armor => {
+            let mut armored = String::new();
+            file.read_to_string(&mut armored).expect("error reading file");
+
+            let key_bytes = keygen::dearmor(&armored).unwrap_or_else(|err| exit_with_error(err));
+            present::zeroize(&mut armored.into_bytes());
+            Secret(key_bytes)
+        }
+        _ => {
+            let mut data: Vec<u8> = Vec::new();
+            file.read_to_end(&mut data).expect("error reading file");
+
+            Secret(decode_input(data, format))
+        }
+    }
+}
+
+fn read_password(input_source: &InputSource) -> Secret {
+    if io::stdin().is_terminal() {
+        return Secret(
+            rpassword::prompt_password("Password: ")
+                .expect("error reading password")
+                .into_bytes(),
+        );
+    }
+
+    if let InputSource::Stdin = *input_source {
+        exit_with_error("cannot read both the password and the input from standard input");
+    }
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    stdin.lock().read_line(&mut line).expect("error reading password");
+    Secret(trim_newline(line.into_bytes()))
+}
+
+fn read_password_from_file(filename: &str) -> Secret {
+    let mut file = File::open(filename).expect("file not found!");
+    let mut password: Vec<u8> = Vec::new();
+    file.read_to_end(&mut password).expect("error reading file");
+
+    Secret(trim_newline(password))
+}
+
+fn trim_newline(mut bytes: Vec<u8>) -> Vec<u8> {
+    if bytes.last() == Some(&b'\n') {
+        bytes.pop();
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+    }
+    bytes
+}
+
+fn derive_key_bytes(
+    password: &[u8],
+    salt: &[u8],
+    kdf: &Kdf,
+    iterations: u32,
+    key_length: KeyLength,
+) -> Secret {
+    let key_length_in_bytes = match key_length {
+        KeyLength::Key80 => present80::KEY_LENGTH_IN_BYTES,
+        _ => present128::KEY_LENGTH_IN_BYTES,
+    };
+
+    let mut key_bytes = vec![0u8; key_length_in_bytes];
+    let result = match *kdf {
+        Kdf::Pbkdf2 => kdf::pbkdf2(password, salt, iterations, &mut key_bytes),
+        #[cfg(feature = "argon2")]
+        Kdf::Argon2 => kdf::argon2(password, salt, &mut key_bytes),
+        #[cfg(not(feature = "argon2"))]
+        Kdf::Argon2 => exit_with_error("this build of present does not support argon2"),
+    };
+
+    if let Err(err) = result {
+        exit_with_error(err);
+    }
+    Secret(key_bytes)
+}
+
+fn read_key_bytes_from_string(s: &str, format: &Format) -> Secret {
+    Secret(decode_input(s.as_bytes().to_vec(), format))
 }
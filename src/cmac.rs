@@ -0,0 +1,136 @@
+use super::BlockCipher;
+
+pub const MAC_SIZE_IN_BYTES: usize = super::BLOCK_SIZE_IN_BYTES;
+
+// The subkey reduction constant for 64-bit blocks given in NIST SP 800-38B.
+const RB: u64 = 0x1b;
+
+fn double(x: u64) -> u64 {
+    if x >> 63 == 1 {
+        (x << 1) ^ RB
+    } else {
+        x << 1
+    }
+}
+
+pub struct Cmac<'a, C: 'a + BlockCipher> {
+    cipher: &'a C,
+    k1: u64,
+    k2: u64,
+    state: u64,
+    buffer: Vec<u8>,
+}
+
+impl<'a, C: BlockCipher> Cmac<'a, C> {
+    pub fn new(cipher: &'a C) -> Cmac<'a, C> {
+        let l = cipher.encrypt_state(0);
+        let k1 = double(l);
+        let k2 = double(k1);
+
+        Cmac {
+            cipher,
+            k1,
+            k2,
+            state: 0,
+            buffer: Vec::with_capacity(super::BLOCK_SIZE_IN_BYTES),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data.iter() {
+            // The last block is only processed in finalize, once we know whether it is full.
+            if self.buffer.len() == super::BLOCK_SIZE_IN_BYTES {
+                let block = super::bytes_to_state(&self.buffer);
+                self.state = self.cipher.encrypt_state(self.state ^ block);
+                self.buffer.clear();
+            }
+            self.buffer.push(*byte);
+        }
+    }
+
+    pub fn finalize(self) -> [u8; MAC_SIZE_IN_BYTES] {
+        let last = if self.buffer.len() == super::BLOCK_SIZE_IN_BYTES {
+            super::bytes_to_state(&self.buffer) ^ self.k1
+        } else {
+            let mut padded = self.buffer.clone();
+            padded.push(0x80);
+            super::bytes_to_state(&padded) ^ self.k2
+        };
+
+        super::state_to_bytes(self.cipher.encrypt_state(self.state ^ last))
+    }
+
+    pub fn verify(self, tag: &[u8]) -> bool {
+        super::constant_time_eq(&self.finalize(), tag)
+    }
+}
+
+pub fn cmac<C: BlockCipher>(cipher: &C, data: &[u8]) -> [u8; MAC_SIZE_IN_BYTES] {
+    let mut mac = Cmac::new(cipher);
+    mac.update(data);
+    mac.finalize()
+}
+
+pub fn verify<C: BlockCipher>(cipher: &C, data: &[u8], tag: &[u8]) -> bool {
+    let mut mac = Cmac::new(cipher);
+    mac.update(data);
+    mac.verify(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{bytes_to_state, present80, state_to_bytes};
+    use super::*;
+
+    #[test]
+    fn test_double() {
+        assert_eq!(0b10, double(1));
+        assert_eq!(RB, double(1 << 63));
+        assert_eq!(0xfffffffffffffffe ^ RB, double(0xffffffffffffffff));
+    }
+
+    #[test]
+    fn test_full_block() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0u8; 10]));
+        let k1 = double(cipher.encrypt_state(0));
+
+        let block = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let expected = cipher.encrypt_block(&state_to_bytes(bytes_to_state(&block) ^ k1));
+        assert_eq!(expected, cmac(&cipher, &block));
+    }
+
+    #[test]
+    fn test_empty_message() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0u8; 10]));
+        let k2 = double(double(cipher.encrypt_state(0)));
+
+        let expected = cipher.encrypt_state((0x80 << 56) ^ k2);
+        assert_eq!(expected, bytes_to_state(&cmac(&cipher, &[])));
+    }
+
+    #[test]
+    fn test_streaming() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x5a; 10]));
+        let data: Vec<u8> = (0..40).collect();
+
+        for split in [0, 1, 8, 9, 16, 39, 40].iter() {
+            let mut mac = Cmac::new(&cipher);
+            mac.update(&data[..*split]);
+            mac.update(&data[*split..]);
+            assert_eq!(cmac(&cipher, &data), mac.finalize());
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x5a; 10]));
+        let mut tag = cmac(&cipher, b"PRESENT");
+
+        assert!(verify(&cipher, b"PRESENT", &tag));
+        assert!(!verify(&cipher, b"PRESENT.", &tag));
+        assert!(!verify(&cipher, b"PRESENT", &tag[..7]));
+
+        tag[0] ^= 1;
+        assert!(!verify(&cipher, b"PRESENT", &tag));
+    }
+}
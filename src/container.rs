@@ -0,0 +1,652 @@
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use getrandom;
+
+use super::eax;
+use super::BlockCipher;
+
+// Version 1 layout, with all integers big-endian:
+//
+//   magic (8) | version (1) | key size in bits (1) | mode (1) | kdf (1) | iterations (4)
+//   | salt length (1) | salt | nonce (8) | chunk size (4) | original length (8)
+//
+// followed by the chunks, each encrypted with EAX and followed by its tag. Every chunk
+// authenticates the whole header, and its nonce is the header nonce, the chunk index and
+// a flag marking the last chunk.
+pub const MAGIC: [u8; 8] = *b"PRESENT\0";
+pub const VERSION: u8 = 1;
+pub const NONCE_SIZE_IN_BYTES: usize = 8;
+pub const SALT_SIZE_IN_BYTES: usize = 16;
+pub const DEFAULT_CHUNK_SIZE: u32 = 1 << 16;
+// The header is read before anything is authenticated, so the chunk size it gives is capped
+// before a buffer of that size is allocated.
+pub const MAX_CHUNK_SIZE: u32 = 1 << 24;
+
+const MAX_SALT_SIZE_IN_BYTES: usize = 255;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeySize {
+    Key80,
+    Key128,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Eax,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kdf {
+    None,
+    Pbkdf2 { salt: Vec<u8>, iterations: u32 },
+    Argon2 { salt: Vec<u8> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub key_size: KeySize,
+    pub mode: Mode,
+    pub kdf: Kdf,
+    pub nonce: [u8; NONCE_SIZE_IN_BYTES],
+    pub chunk_size: u32,
+    pub length: u64,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    InvalidHeader,
+    AuthenticationFailed,
+    LengthMismatch,
+    Truncated,
+    TrailingData,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "{}", err),
+            Error::InvalidMagic => write!(f, "not a PRESENT container"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported container version {}", version)
+            }
+            Error::InvalidHeader => write!(f, "invalid container header"),
+            Error::AuthenticationFailed => {
+                write!(f, "authentication failed: wrong key or corrupted data")
+            }
+            Error::LengthMismatch => write!(f, "data length does not match the header"),
+            Error::Truncated => write!(f, "container is truncated"),
+            Error::TrailingData => write!(f, "unexpected data after the last chunk"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+impl KeySize {
+    pub fn bits(&self) -> u8 {
+        match *self {
+            KeySize::Key80 => 80,
+            KeySize::Key128 => 128,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Option<KeySize> {
+        match bits {
+            80 => Some(KeySize::Key80),
+            128 => Some(KeySize::Key128),
+            _ => None,
+        }
+    }
+}
+
+pub fn random_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_SIZE_IN_BYTES];
+    getrandom::getrandom(&mut salt).expect("error generating salt");
+    salt
+}
+
+impl Header {
+    pub fn new(key_size: KeySize, kdf: Kdf, length: u64) -> Header {
+        let mut nonce = [0u8; NONCE_SIZE_IN_BYTES];
+        getrandom::getrandom(&mut nonce).expect("error generating nonce");
+
+        Header {
+            key_size,
+            mode: Mode::Eax,
+            kdf,
+            nonce,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            length,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let salt_length = match self.kdf {
+            Kdf::None => 0,
+            Kdf::Pbkdf2 { ref salt, .. } | Kdf::Argon2 { ref salt } => salt.len(),
+        };
+        if salt_length > MAX_SALT_SIZE_IN_BYTES
+            || self.chunk_size == 0
+            || self.chunk_size > MAX_CHUNK_SIZE
+        {
+            return Err(Error::InvalidHeader);
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (kdf, iterations, salt): (u8, u32, &[u8]) = match self.kdf {
+            Kdf::None => (0, 0, &[]),
+            Kdf::Pbkdf2 {
+                ref salt,
+                iterations,
+            } => (1, iterations, salt),
+            Kdf::Argon2 { ref salt } => (2, 0, salt),
+        };
+        let mode = match self.mode {
+            Mode::Eax => 1,
+        };
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.key_size.bits());
+        bytes.push(mode);
+        bytes.push(kdf);
+        bytes.extend_from_slice(&iterations.to_be_bytes());
+        bytes.push(salt.len() as u8);
+        bytes.extend_from_slice(salt);
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.chunk_size.to_be_bytes());
+        bytes.extend_from_slice(&self.length.to_be_bytes());
+        bytes
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Header, Error> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic).map_err(truncated)?;
+        if magic != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+
+        let mut fields = [0u8; 9];
+        reader.read_exact(&mut fields).map_err(truncated)?;
+        if fields[0] != VERSION {
+            return Err(Error::UnsupportedVersion(fields[0]));
+        }
+
+        let key_size = KeySize::from_bits(fields[1]).ok_or(Error::InvalidHeader)?;
+        let mode = match fields[2] {
+            1 => Mode::Eax,
+            _ => return Err(Error::InvalidHeader),
+        };
+        let iterations = u32::from_be_bytes([fields[4], fields[5], fields[6], fields[7]]);
+
+        let mut salt = vec![0u8; fields[8] as usize];
+        reader.read_exact(&mut salt).map_err(truncated)?;
+
+        let kdf = match (fields[3], salt.is_empty()) {
+            (0, true) if iterations == 0 => Kdf::None,
+            (1, _) if iterations > 0 => Kdf::Pbkdf2 { salt, iterations },
+            (2, _) if iterations == 0 => Kdf::Argon2 { salt },
+            _ => return Err(Error::InvalidHeader),
+        };
+
+        let mut nonce = [0u8; NONCE_SIZE_IN_BYTES];
+        reader.read_exact(&mut nonce).map_err(truncated)?;
+
+        let mut chunk_size = [0u8; 4];
+        reader.read_exact(&mut chunk_size).map_err(truncated)?;
+        let mut length = [0u8; 8];
+        reader.read_exact(&mut length).map_err(truncated)?;
+
+        let header = Header {
+            key_size,
+            mode,
+            kdf,
+            nonce,
+            chunk_size: u32::from_be_bytes(chunk_size),
+            length: u64::from_be_bytes(length),
+        };
+        header.validate()?;
+
+        Ok(header)
+    }
+
+    fn num_chunks(&self) -> u64 {
+        let chunk_size = self.chunk_size as u64;
+        cmp::max(1, self.length.div_ceil(chunk_size))
+    }
+
+    fn chunk_nonce(&self, index: u64) -> [u8; NONCE_SIZE_IN_BYTES + 9] {
+        let mut nonce = [0u8; NONCE_SIZE_IN_BYTES + 9];
+        nonce[..NONCE_SIZE_IN_BYTES].copy_from_slice(&self.nonce);
+        nonce[NONCE_SIZE_IN_BYTES..NONCE_SIZE_IN_BYTES + 8].copy_from_slice(&index.to_be_bytes());
+        if index + 1 == self.num_chunks() {
+            nonce[NONCE_SIZE_IN_BYTES + 8] = 1;
+        }
+        nonce
+    }
+}
+
+fn truncated(err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Truncated,
+        _ => Error::Io(err),
+    }
+}
+
+pub struct Writer<W: Write, C: BlockCipher> {
+    inner: W,
+    cipher: C,
+    header: Header,
+    header_bytes: Vec<u8>,
+    buffer: Vec<u8>,
+    chunk_index: u64,
+    written: u64,
+    progress: Option<Box<dyn FnMut(u64)>>,
+}
+
+impl<W: Write, C: BlockCipher> Writer<W, C> {
+    pub fn new(mut inner: W, cipher: C, header: Header) -> Result<Writer<W, C>, Error> {
+        header.validate()?;
+        let header_bytes = header.to_bytes();
+        inner.write_all(&header_bytes)?;
+
+        Ok(Writer {
+            inner,
+            cipher,
+            buffer: Vec::with_capacity(header.chunk_size as usize),
+            header,
+            header_bytes,
+            chunk_index: 0,
+            written: 0,
+            progress: None,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    // Called with the number of bytes encrypted so far after every chunk.
+    pub fn set_progress<F: FnMut(u64) + 'static>(&mut self, progress: F) {
+        self.progress = Some(Box::new(progress));
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        let nonce = self.header.chunk_nonce(self.chunk_index);
+        let tag = eax::encrypt(&self.cipher, &nonce, &self.header_bytes, &mut self.buffer);
+
+        self.inner.write_all(&self.buffer)?;
+        self.inner.write_all(&tag)?;
+        self.buffer.clear();
+        self.chunk_index += 1;
+
+        if let Some(ref mut progress) = self.progress {
+            progress(self.written);
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.written != self.header.length {
+            return Err(Error::LengthMismatch);
+        }
+
+        self.write_chunk()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, C: BlockCipher> Write for Writer<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() as u64 > self.header.length - self.written {
+            return Err(Error::LengthMismatch.into());
+        }
+
+        let chunk_size = self.header.chunk_size as usize;
+        let mut data = buf;
+        while !data.is_empty() {
+            // A full chunk is only written once more data arrives, since the last chunk has to
+            // be marked as such.
+            if self.buffer.len() == chunk_size {
+                self.write_chunk()?;
+            }
+
+            let n = cmp::min(chunk_size - self.buffer.len(), data.len());
+            self.buffer.extend_from_slice(&data[..n]);
+            self.written += n as u64;
+            data = &data[n..];
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct Reader<R: Read, C: BlockCipher> {
+    inner: R,
+    cipher: C,
+    header: Header,
+    header_bytes: Vec<u8>,
+    buffer: Vec<u8>,
+    position: usize,
+    chunk_index: u64,
+    remaining: u64,
+    progress: Option<Box<dyn FnMut(u64)>>,
+}
+
+impl<R: Read, C: BlockCipher> Reader<R, C> {
+    // The reader must be positioned just after the header, as left by Header::read_from.
+    pub fn new(inner: R, cipher: C, header: Header) -> Reader<R, C> {
+        Reader {
+            inner,
+            cipher,
+            header_bytes: header.to_bytes(),
+            remaining: header.length,
+            header,
+            buffer: Vec::new(),
+            position: 0,
+            chunk_index: 0,
+            progress: None,
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    // Called with the number of bytes decrypted so far after every chunk.
+    pub fn set_progress<F: FnMut(u64) + 'static>(&mut self, progress: F) {
+        self.progress = Some(Box::new(progress));
+    }
+
+    fn read_chunk(&mut self) -> Result<bool, Error> {
+        if self.chunk_index == self.header.num_chunks() {
+            let mut byte = [0u8; 1];
+            return match self.inner.read(&mut byte)? {
+                0 => Ok(false),
+                _ => Err(Error::TrailingData),
+            };
+        }
+
+        let size = cmp::min(self.remaining, self.header.chunk_size as u64) as usize;
+        self.buffer.resize(size, 0);
+        self.inner.read_exact(&mut self.buffer).map_err(truncated)?;
+        let mut tag = [0u8; eax::TAG_SIZE_IN_BYTES];
+        self.inner.read_exact(&mut tag).map_err(truncated)?;
+
+        let nonce = self.header.chunk_nonce(self.chunk_index);
+        eax::decrypt(
+            &self.cipher,
+            &nonce,
+            &self.header_bytes,
+            &mut self.buffer,
+            &tag,
+        )
+        .map_err(|_| Error::AuthenticationFailed)?;
+
+        self.position = 0;
+        self.remaining -= size as u64;
+        self.chunk_index += 1;
+
+        if let Some(ref mut progress) = self.progress {
+            progress(self.header.length - self.remaining);
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read, C: BlockCipher> Read for Reader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if !self.read_chunk()? {
+                return Ok(0);
+            }
+        }
+
+        let n = cmp::min(buf.len(), self.buffer.len() - self.position);
+        buf[..n].copy_from_slice(&self.buffer[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+pub fn encrypt<C: BlockCipher>(cipher: C, key_size: KeySize, kdf: Kdf, data: &[u8]) -> Vec<u8> {
+    let header = Header::new(key_size, kdf, data.len() as u64);
+    let mut writer = Writer::new(Vec::new(), cipher, header).expect("error writing header");
+    writer.write_all(data).expect("error encrypting data");
+    writer.finish().expect("error encrypting data")
+}
+
+pub fn decrypt<C: BlockCipher>(cipher: C, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut data = data;
+    let header = Header::read_from(&mut data)?;
+
+    let mut reader = Reader::new(data, cipher, header);
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted)?;
+    Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{present128, present80};
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn seal(cipher: &present80::Cipher, chunk_size: u32, data: &[u8]) -> Vec<u8> {
+        let mut header = Header::new(KeySize::Key80, Kdf::None, data.len() as u64);
+        header.chunk_size = chunk_size;
+
+        let mut writer = Writer::new(Vec::new(), cipher, header).unwrap();
+        for piece in data.chunks(7) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn open(cipher: &present80::Cipher, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut data = data;
+        let header = Header::read_from(&mut data)?;
+        let mut reader = Reader::new(data, cipher, header);
+
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).map_err(|err| {
+            match err.into_inner().map(|err| err.downcast::<Error>()) {
+                Some(Ok(err)) => *err,
+                _ => Error::InvalidHeader,
+            }
+        })?;
+        Ok(decrypted)
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let kdf = Kdf::Pbkdf2 {
+            salt: vec![1, 2, 3],
+            iterations: 1000,
+        };
+        let header = Header::new(KeySize::Key128, kdf, 12345);
+        let bytes = header.to_bytes();
+        assert_eq!(&MAGIC[..], &bytes[..8]);
+
+        assert_eq!(header, Header::read_from(&mut &bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        let data: Vec<u8> = (0..100).collect();
+
+        for length in [0, 1, 15, 16, 17, 32, 100].iter() {
+            let sealed = seal(&cipher, 16, &data[..*length]);
+            assert_eq!(&data[..*length], &open(&cipher, &sealed).unwrap()[..]);
+        }
+    }
+
+    #[test]
+    fn test_convenience() {
+        let cipher = present128::Cipher::new(present128::Key::new(&[0x42; 16]));
+        let sealed = encrypt(&cipher, KeySize::Key128, Kdf::None, b"PRESENT");
+        assert_eq!(b"PRESENT".to_vec(), decrypt(&cipher, &sealed).unwrap());
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        let wrong = present80::Cipher::new(present80::Key::new(&[0x24; 10]));
+
+        let sealed = seal(&cipher, 16, b"attack at dawn");
+        match open(&wrong, &sealed) {
+            Err(Error::AuthenticationFailed) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_tampering() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        let data: Vec<u8> = (0..40).collect();
+        let sealed = seal(&cipher, 16, &data);
+        let header_length = Header::read_from(&mut &sealed[..])
+            .unwrap()
+            .to_bytes()
+            .len();
+        let chunk = 16 + eax::TAG_SIZE_IN_BYTES;
+
+        let mut truncated = sealed.clone();
+        truncated.truncate(header_length + 2 * chunk);
+        match open(&cipher, &truncated) {
+            Err(Error::Truncated) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let mut reordered = sealed[..header_length].to_vec();
+        reordered.extend_from_slice(&sealed[header_length + chunk..header_length + 2 * chunk]);
+        reordered.extend_from_slice(&sealed[header_length..header_length + chunk]);
+        reordered.extend_from_slice(&sealed[header_length + 2 * chunk..]);
+        match open(&cipher, &reordered) {
+            Err(Error::AuthenticationFailed) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let mut trailing = sealed.clone();
+        trailing.push(0);
+        match open(&cipher, &trailing) {
+            Err(Error::TrailingData) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // Shortening the declared length must not let a truncated container through.
+        let mut header = Header::read_from(&mut &sealed[..]).unwrap();
+        header.length = 32;
+        let mut relabelled = header.to_bytes();
+        relabelled.extend_from_slice(&sealed[header_length..header_length + 2 * chunk]);
+        match open(&cipher, &relabelled) {
+            Err(Error::AuthenticationFailed) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        let sealed = seal(&cipher, 16, b"PRESENT");
+
+        let mut bad_magic = sealed.clone();
+        bad_magic[0] = b'X';
+        match Header::read_from(&mut &bad_magic[..]) {
+            Err(Error::InvalidMagic) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let mut bad_version = sealed.clone();
+        bad_version[8] = 2;
+        match Header::read_from(&mut &bad_version[..]) {
+            Err(Error::UnsupportedVersion(2)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        match Header::read_from(&mut &sealed[..12]) {
+            Err(Error::Truncated) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let mut header = Header::new(KeySize::Key80, Kdf::None, 0);
+        header.chunk_size = u32::MAX;
+        match Header::read_from(&mut &header.to_bytes()[..]) {
+            Err(Error::InvalidHeader) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        let header = Header::new(KeySize::Key80, Kdf::None, 4);
+
+        let mut writer = Writer::new(Vec::new(), &cipher, header.clone()).unwrap();
+        assert!(writer.write_all(b"PRESENT").is_err());
+
+        let mut writer = Writer::new(Vec::new(), &cipher, header).unwrap();
+        writer.write_all(b"PRE").unwrap();
+        match writer.finish() {
+            Err(Error::LengthMismatch) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        let data: Vec<u8> = (0..40).collect();
+        let mut header = Header::new(KeySize::Key80, Kdf::None, data.len() as u64);
+        header.chunk_size = 16;
+
+        let written = Rc::new(RefCell::new(Vec::new()));
+        let mut writer = Writer::new(Vec::new(), &cipher, header.clone()).unwrap();
+        let progress = written.clone();
+        writer.set_progress(move |n| progress.borrow_mut().push(n));
+        writer.write_all(&data).unwrap();
+        let sealed = writer.finish().unwrap();
+        assert_eq!(vec![16, 32, 40], *written.borrow());
+
+        let read = Rc::new(RefCell::new(Vec::new()));
+        let mut reader = Reader::new(&sealed[header.to_bytes().len()..], &cipher, header);
+        let progress = read.clone();
+        reader.set_progress(move |n| progress.borrow_mut().push(n));
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(data, decrypted);
+        assert_eq!(vec![16, 32, 40], *read.borrow());
+    }
+}
@@ -0,0 +1,304 @@
+use std::error;
+use std::fmt;
+
+#[cfg(feature = "rand_core")]
+use rand_core::{self, CryptoRng, RngCore, SeedableRng};
+#[cfg(feature = "rand_core")]
+use getrandom;
+
+use super::present128;
+use super::BlockCipher;
+
+// CTR_DRBG from NIST SP 800-90A without a derivation function, instantiated with
+// PRESENT-128. The limits are the ones the standard gives for TDEA, the other 64-bit block
+// cipher it allows.
+pub const SEED_LENGTH_IN_BYTES: usize =
+    present128::KEY_LENGTH_IN_BYTES + super::BLOCK_SIZE_IN_BYTES;
+pub const MAX_REQUEST_SIZE_IN_BYTES: usize = 1 << 10;
+pub const RESEED_INTERVAL: u64 = 1 << 32;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidEntropyLength,
+    InvalidInputLength,
+    RequestTooLarge,
+    ReseedRequired,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidEntropyLength => {
+                write!(f, "entropy input must be {} bytes", SEED_LENGTH_IN_BYTES)
+            }
+            Error::InvalidInputLength => write!(
+                f,
+                "personalization string or additional input longer than {} bytes",
+                SEED_LENGTH_IN_BYTES
+            ),
+            Error::RequestTooLarge => write!(
+                f,
+                "cannot generate more than {} bytes per request",
+                MAX_REQUEST_SIZE_IN_BYTES
+            ),
+            Error::ReseedRequired => write!(f, "reseed required"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+pub struct CtrDrbg {
+    cipher: present128::Cipher,
+    v: u64,
+    reseed_counter: u64,
+}
+
+fn seed_material(input: &[u8], other: &[u8]) -> Result<[u8; SEED_LENGTH_IN_BYTES], Error> {
+    if input.len() != SEED_LENGTH_IN_BYTES {
+        return Err(Error::InvalidEntropyLength);
+    }
+    if other.len() > SEED_LENGTH_IN_BYTES {
+        return Err(Error::InvalidInputLength);
+    }
+
+    let mut seed_material = [0u8; SEED_LENGTH_IN_BYTES];
+    seed_material.copy_from_slice(input);
+    for (x, y) in seed_material.iter_mut().zip(other.iter()) {
+        *x ^= *y;
+    }
+    Ok(seed_material)
+}
+
+impl CtrDrbg {
+    pub fn new(entropy_input: &[u8], personalization_string: &[u8]) -> Result<CtrDrbg, Error> {
+        let seed_material = seed_material(entropy_input, personalization_string)?;
+
+        let mut drbg = CtrDrbg {
+            cipher: present128::Cipher::new(present128::Key::new(&[0u8; 16])),
+            v: 0,
+            reseed_counter: 1,
+        };
+        drbg.update(&seed_material);
+
+        Ok(drbg)
+    }
+
+    fn update(&mut self, provided_data: &[u8; SEED_LENGTH_IN_BYTES]) {
+        let mut temp = [0u8; SEED_LENGTH_IN_BYTES];
+        for block in temp.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+            self.v = self.v.wrapping_add(1);
+            block.copy_from_slice(&super::state_to_bytes(self.cipher.encrypt_state(self.v)));
+        }
+
+        for (x, y) in temp.iter_mut().zip(provided_data.iter()) {
+            *x ^= *y;
+        }
+
+        let key = present128::Key::new(&temp[..present128::KEY_LENGTH_IN_BYTES]);
+        self.cipher = present128::Cipher::new(key);
+        self.v = super::bytes_to_state(&temp[present128::KEY_LENGTH_IN_BYTES..]);
+        super::zeroize(&mut temp);
+    }
+
+    pub fn reseed(&mut self, entropy_input: &[u8], additional_input: &[u8]) -> Result<(), Error> {
+        let seed_material = seed_material(entropy_input, additional_input)?;
+        self.update(&seed_material);
+        self.reseed_counter = 1;
+
+        Ok(())
+    }
+
+    pub fn generate(&mut self, output: &mut [u8], additional_input: &[u8]) -> Result<(), Error> {
+        if output.len() > MAX_REQUEST_SIZE_IN_BYTES {
+            return Err(Error::RequestTooLarge);
+        }
+        if additional_input.len() > SEED_LENGTH_IN_BYTES {
+            return Err(Error::InvalidInputLength);
+        }
+        if self.reseed_counter > RESEED_INTERVAL {
+            return Err(Error::ReseedRequired);
+        }
+
+        let mut additional = [0u8; SEED_LENGTH_IN_BYTES];
+        additional[..additional_input.len()].copy_from_slice(additional_input);
+        if !additional_input.is_empty() {
+            self.update(&additional);
+        }
+
+        for chunk in output.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+            self.v = self.v.wrapping_add(1);
+            let block = super::state_to_bytes(self.cipher.encrypt_state(self.v));
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        // Updating the state after every request provides backtracking resistance.
+        self.update(&additional);
+        self.reseed_counter += 1;
+
+        Ok(())
+    }
+
+    pub fn reseed_required(&self) -> bool {
+        self.reseed_counter > RESEED_INTERVAL
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl RngCore for CtrDrbg {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest).expect("error generating random bytes")
+    }
+
+    // Generators handed out through RngCore reseed themselves from the operating system once
+    // the interval runs out, so fill_bytes only panics if getrandom fails.
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        for chunk in dest.chunks_mut(MAX_REQUEST_SIZE_IN_BYTES) {
+            if self.reseed_required() {
+                let mut entropy_input = [0u8; SEED_LENGTH_IN_BYTES];
+                getrandom::getrandom(&mut entropy_input).map_err(rand_core::Error::new)?;
+                self.reseed(&entropy_input, &[]).map_err(rand_core::Error::new)?;
+                super::zeroize(&mut entropy_input);
+            }
+            self.generate(chunk, &[]).map_err(rand_core::Error::new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl CryptoRng for CtrDrbg {}
+
+#[cfg(feature = "rand_core")]
+impl SeedableRng for CtrDrbg {
+    type Seed = [u8; SEED_LENGTH_IN_BYTES];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        CtrDrbg::new(&seed, &[]).expect("seed has the correct length")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{bytes_to_state, state_to_bytes};
+    use super::*;
+
+    #[test]
+    fn test_generate_known_answer() {
+        let mut drbg = CtrDrbg::new(&[0u8; SEED_LENGTH_IN_BYTES], &[]).unwrap();
+        let mut output = [0u8; 16];
+        drbg.generate(&mut output, &[]).unwrap();
+
+        // Instantiating with an all-zero seed sets K || V to the encryption of 1, 2 and 3
+        // under the zero key.
+        let zero = present128::Cipher::new(present128::Key::new(&[0u8; 16]));
+        let mut temp: Vec<u8> = Vec::new();
+        for i in 1..4 {
+            temp.extend(zero.encrypt_block(&state_to_bytes(i)).iter());
+        }
+        let cipher = present128::Cipher::new(present128::Key::new(&temp[..16]));
+        let v = bytes_to_state(&temp[16..]);
+
+        let mut expected: Vec<u8> = Vec::new();
+        expected.extend(cipher.encrypt_block(&state_to_bytes(v + 1)).iter());
+        expected.extend(cipher.encrypt_block(&state_to_bytes(v + 2)).iter());
+        assert_eq!(&expected[..], &output[..]);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let entropy = [0x42u8; SEED_LENGTH_IN_BYTES];
+        let mut a = CtrDrbg::new(&entropy, b"device-1").unwrap();
+        let mut b = CtrDrbg::new(&entropy, b"device-1").unwrap();
+        let mut c = CtrDrbg::new(&entropy, b"device-2").unwrap();
+
+        let (mut x, mut y, mut z) = ([0u8; 37], [0u8; 37], [0u8; 37]);
+        a.generate(&mut x, &[]).unwrap();
+        b.generate(&mut y, &[]).unwrap();
+        c.generate(&mut z, &[]).unwrap();
+        assert_eq!(&x[..], &y[..]);
+        assert_ne!(&x[..], &z[..]);
+
+        a.generate(&mut x, &[]).unwrap();
+        assert_ne!(&x[..], &y[..]);
+    }
+
+    #[test]
+    fn test_reseed() {
+        let entropy = [0x42u8; SEED_LENGTH_IN_BYTES];
+        let mut a = CtrDrbg::new(&entropy, &[]).unwrap();
+        let mut b = CtrDrbg::new(&entropy, &[]).unwrap();
+
+        a.reseed(&[0x24u8; SEED_LENGTH_IN_BYTES], b"reseed").unwrap();
+
+        let (mut x, mut y) = ([0u8; 8], [0u8; 8]);
+        a.generate(&mut x, &[]).unwrap();
+        b.generate(&mut y, &[]).unwrap();
+        assert_ne!(x, y);
+    }
+
+    #[test]
+    fn test_reseed_required() {
+        let mut drbg = CtrDrbg::new(&[0u8; SEED_LENGTH_IN_BYTES], &[]).unwrap();
+        drbg.reseed_counter = RESEED_INTERVAL + 1;
+        assert!(drbg.reseed_required());
+
+        let mut output = [0u8; 8];
+        assert_eq!(Err(Error::ReseedRequired), drbg.generate(&mut output, &[]));
+
+        drbg.reseed(&[1u8; SEED_LENGTH_IN_BYTES], &[]).unwrap();
+        assert_eq!(Ok(()), drbg.generate(&mut output, &[]));
+    }
+
+    #[test]
+    fn test_invalid_lengths() {
+        assert_eq!(
+            Some(Error::InvalidEntropyLength),
+            CtrDrbg::new(&[0u8; 16], &[]).err()
+        );
+        assert_eq!(
+            Some(Error::InvalidInputLength),
+            CtrDrbg::new(&[0u8; SEED_LENGTH_IN_BYTES], &[0u8; 25]).err()
+        );
+
+        let mut drbg = CtrDrbg::new(&[0u8; SEED_LENGTH_IN_BYTES], &[]).unwrap();
+        let mut output = vec![0u8; MAX_REQUEST_SIZE_IN_BYTES + 1];
+        assert_eq!(Err(Error::RequestTooLarge), drbg.generate(&mut output, &[]));
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn test_rng_core() {
+        let mut a = CtrDrbg::from_seed([7u8; SEED_LENGTH_IN_BYTES]);
+        let mut b = CtrDrbg::new(&[7u8; SEED_LENGTH_IN_BYTES], &[]).unwrap();
+
+        let mut x = vec![0u8; 3000];
+        a.fill_bytes(&mut x);
+
+        let mut y = vec![0u8; 3000];
+        for chunk in y.chunks_mut(MAX_REQUEST_SIZE_IN_BYTES) {
+            b.generate(chunk, &[]).unwrap();
+        }
+        assert_eq!(x, y);
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn test_rng_core_reseeds() {
+        let mut drbg = CtrDrbg::from_seed([7u8; SEED_LENGTH_IN_BYTES]);
+        drbg.reseed_counter = RESEED_INTERVAL + 1;
+
+        let mut output = [0u8; 8];
+        drbg.fill_bytes(&mut output);
+        assert!(!drbg.reseed_required());
+        assert_eq!(2, drbg.reseed_counter);
+    }
+}
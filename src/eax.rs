@@ -0,0 +1,144 @@
+use std::error;
+use std::fmt;
+
+use super::cmac::Cmac;
+use super::BlockCipher;
+
+pub const TAG_SIZE_IN_BYTES: usize = super::BLOCK_SIZE_IN_BYTES;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    AuthenticationFailed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::AuthenticationFailed => write!(f, "authentication failed"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+fn omac<C: BlockCipher>(cipher: &C, t: u8, data: &[u8]) -> u64 {
+    let mut tweak = [0u8; super::BLOCK_SIZE_IN_BYTES];
+    tweak[super::BLOCK_SIZE_IN_BYTES - 1] = t;
+
+    let mut mac = Cmac::new(cipher);
+    mac.update(&tweak);
+    mac.update(data);
+    super::bytes_to_state(&mac.finalize())
+}
+
+fn ctr<C: BlockCipher>(cipher: &C, counter: u64, data: &mut [u8]) {
+    let mut counter = counter;
+    for chunk in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        let keystream = super::state_to_bytes(cipher.encrypt_state(counter));
+        for (x, y) in chunk.iter_mut().zip(keystream.iter()) {
+            *x ^= *y;
+        }
+        counter = counter.wrapping_add(1);
+    }
+}
+
+// EAX mode (Bellare, Rogaway and Wagner) encrypts data in place and returns the tag.
+pub fn encrypt<C: BlockCipher>(
+    cipher: &C,
+    nonce: &[u8],
+    header: &[u8],
+    data: &mut [u8],
+) -> [u8; TAG_SIZE_IN_BYTES] {
+    let n = omac(cipher, 0, nonce);
+    let h = omac(cipher, 1, header);
+
+    ctr(cipher, n, data);
+    let c = omac(cipher, 2, data);
+
+    super::state_to_bytes(n ^ h ^ c)
+}
+
+// The tag is checked before anything is decrypted, so data is left untouched on failure.
+pub fn decrypt<C: BlockCipher>(
+    cipher: &C,
+    nonce: &[u8],
+    header: &[u8],
+    data: &mut [u8],
+    tag: &[u8],
+) -> Result<(), Error> {
+    let n = omac(cipher, 0, nonce);
+    let h = omac(cipher, 1, header);
+    let c = omac(cipher, 2, data);
+
+    if !super::constant_time_eq(&super::state_to_bytes(n ^ h ^ c), tag) {
+        return Err(Error::AuthenticationFailed);
+    }
+
+    ctr(cipher, n, data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{bytes_to_state, cmac, present128, present80, state_to_bytes};
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = present128::Cipher::new(present128::Key::new(&[0x42; 16]));
+        let plaintext: Vec<u8> = (0..37).collect();
+
+        let mut data = plaintext.clone();
+        let tag = encrypt(&cipher, b"nonce", b"header", &mut data);
+        assert_ne!(plaintext, data);
+
+        decrypt(&cipher, b"nonce", b"header", &mut data, &tag).unwrap();
+        assert_eq!(plaintext, data);
+    }
+
+    #[test]
+    fn test_empty_message() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0u8; 10]));
+
+        let tag = encrypt(&cipher, &[], &[], &mut []);
+
+        let mut expected = 0u64;
+        for t in 0..3 {
+            expected ^= bytes_to_state(&cmac::cmac(&cipher, &[0, 0, 0, 0, 0, 0, 0, t]));
+        }
+        assert_eq!(state_to_bytes(expected), tag);
+    }
+
+    #[test]
+    fn test_tampering() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        let mut data = b"attack at dawn".to_vec();
+        let tag = encrypt(&cipher, b"nonce", b"header", &mut data);
+        let ciphertext = data.clone();
+
+        let mut tampered = ciphertext.clone();
+        tampered[3] ^= 0x80;
+        assert_eq!(
+            Err(Error::AuthenticationFailed),
+            decrypt(&cipher, b"nonce", b"header", &mut tampered, &tag)
+        );
+
+        let mut data = ciphertext.clone();
+        assert_eq!(
+            Err(Error::AuthenticationFailed),
+            decrypt(&cipher, b"nonce", b"Header", &mut data, &tag)
+        );
+        assert_eq!(
+            Err(Error::AuthenticationFailed),
+            decrypt(&cipher, b"Nonce", b"header", &mut data, &tag)
+        );
+        assert_eq!(ciphertext, data);
+
+        let mut bad_tag = tag;
+        bad_tag[7] ^= 1;
+        assert_eq!(
+            Err(Error::AuthenticationFailed),
+            decrypt(&cipher, b"nonce", b"header", &mut data, &bad_tag)
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::error;
+use std::fmt;
+
+const BASE64_STANDARD: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL_SAFE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+const ARMOR_LINE_LENGTH: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub struct Armored {
+    pub label: String,
+    pub headers: Vec<(String, String)>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidCharacter(char),
+    InvalidLength,
+    InvalidArmor,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            Error::InvalidLength => write!(f, "invalid length"),
+            Error::InvalidArmor => write!(f, "missing or mismatched BEGIN and END lines"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+fn encode(alphabet: &[u8], bits: u32, padded_length: usize, data: &[u8]) -> String {
+    let mask = (1 << bits) - 1;
+    let mut encoded = String::new();
+    let mut buffer = 0u32;
+    let mut buffered_bits = 0;
+
+    for byte in data.iter() {
+        buffer = (buffer << 8) | *byte as u32;
+        buffered_bits += 8;
+        while buffered_bits >= bits {
+            buffered_bits -= bits;
+            encoded.push(alphabet[((buffer >> buffered_bits) & mask) as usize] as char);
+        }
+    }
+    if buffered_bits > 0 {
+        encoded.push(alphabet[((buffer << (bits - buffered_bits)) & mask) as usize] as char);
+    }
+
+    while padded_length > 0 && !encoded.len().is_multiple_of(padded_length) {
+        encoded.push('=');
+    }
+    encoded
+}
+
+// Whitespace is skipped so that wrapped lines can be pasted back in, and trailing padding is
+// optional.
+fn decode(alphabet: &[u8], bits: u32, encoded: &str) -> Result<Vec<u8>, Error> {
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut buffer = 0u32;
+    let mut buffered_bits = 0;
+
+    let encoded = encoded.trim_end_matches(|c: char| c == '=' || c.is_whitespace());
+    for c in encoded.chars().filter(|c| !c.is_whitespace()) {
+        let value = alphabet
+            .iter()
+            .position(|&x| x as char == c)
+            .ok_or(Error::InvalidCharacter(c))?;
+
+        buffer = (buffer << bits) | value as u32;
+        buffered_bits += bits;
+        if buffered_bits >= 8 {
+            buffered_bits -= 8;
+            decoded.push((buffer >> buffered_bits) as u8);
+        }
+    }
+
+    // Leftover bits only ever come from padding out the last byte, never a whole character.
+    if buffered_bits >= bits {
+        return Err(Error::InvalidLength);
+    }
+    Ok(decoded)
+}
+
+pub fn base64_encode(data: &[u8]) -> String {
+    encode(BASE64_STANDARD, 6, 4, data)
+}
+
+pub fn base64_decode(encoded: &str) -> Result<Vec<u8>, Error> {
+    decode(BASE64_STANDARD, 6, encoded)
+}
+
+// The URL-safe alphabet is written without padding, since = needs escaping in URLs too.
+pub fn base64_url_encode(data: &[u8]) -> String {
+    encode(BASE64_URL_SAFE, 6, 0, data)
+}
+
+pub fn base64_url_decode(encoded: &str) -> Result<Vec<u8>, Error> {
+    decode(BASE64_URL_SAFE, 6, encoded)
+}
+
+pub fn base32_encode(data: &[u8]) -> String {
+    encode(BASE32, 5, 8, data)
+}
+
+pub fn base32_decode(encoded: &str) -> Result<Vec<u8>, Error> {
+    decode(BASE32, 5, &encoded.to_ascii_uppercase())
+}
+
+// PEM-style armor: base64 wrapped at 64 columns between BEGIN and END lines.
+pub fn armor(label: &str, data: &[u8]) -> String {
+    armor_with_headers(label, &[], data)
+}
+
+// Headers are written as "Name: value" lines followed by a blank line, as in RFC 1421.
+pub fn armor_with_headers(label: &str, headers: &[(&str, &str)], data: &[u8]) -> String {
+    let encoded = base64_encode(data);
+
+    let mut armored = format!("-----BEGIN {}-----\n", label);
+    for &(name, value) in headers.iter() {
+        armored.push_str(&format!("{}: {}\n", name, value));
+    }
+    if !headers.is_empty() {
+        armored.push('\n');
+    }
+    for line in encoded.as_bytes().chunks(ARMOR_LINE_LENGTH) {
+        armored.push_str(&String::from_utf8_lossy(line));
+        armored.push('\n');
+    }
+    armored.push_str(&format!("-----END {}-----\n", label));
+    armored
+}
+
+// Returns the label and the data. Anything around the BEGIN and END lines is ignored.
+pub fn dearmor(armored: &str) -> Result<(String, Vec<u8>), Error> {
+    dearmor_with_headers(armored).map(|armored| (armored.label, armored.data))
+}
+
+pub fn dearmor_with_headers(armored: &str) -> Result<Armored, Error> {
+    let mut lines = armored.lines().map(|line| line.trim());
+
+    let label = lines
+        .by_ref()
+        .filter_map(|line| {
+            line.strip_prefix("-----BEGIN ")
+                .and_then(|line| line.strip_suffix("-----"))
+        })
+        .next()
+        .ok_or(Error::InvalidArmor)?
+        .to_string();
+
+    let end = format!("-----END {}-----", label);
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut encoded = String::new();
+    for line in lines {
+        if line == end {
+            let data = base64_decode(&encoded)?;
+            return Ok(Armored {
+                label,
+                headers,
+                data,
+            });
+        }
+
+        // Base64 never contains a colon, so there is no mistaking a header for data.
+        match line.find(':') {
+            Some(i) if encoded.is_empty() => headers.push((
+                line[..i].trim().to_string(),
+                line[i + 1..].trim().to_string(),
+            )),
+            _ => encoded.push_str(line),
+        }
+    }
+
+    Err(Error::InvalidArmor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors from RFC 4648.
+    const VECTORS: [(&str, &str, &str); 7] = [
+        ("", "", ""),
+        ("f", "Zg==", "MY======"),
+        ("fo", "Zm8=", "MZXQ===="),
+        ("foo", "Zm9v", "MZXW6==="),
+        ("foob", "Zm9vYg==", "MZXW6YQ="),
+        ("fooba", "Zm9vYmE=", "MZXW6YTB"),
+        ("foobar", "Zm9vYmFy", "MZXW6YTBOI======"),
+    ];
+
+    #[test]
+    fn test_base64() {
+        for &(data, base64, _) in VECTORS.iter() {
+            assert_eq!(base64, base64_encode(data.as_bytes()));
+            assert_eq!(data.as_bytes(), &base64_decode(base64).unwrap()[..]);
+            assert_eq!(
+                data.as_bytes(),
+                &base64_decode(base64.trim_end_matches('=')).unwrap()[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_base64_url() {
+        let data = [0xfbu8, 0xff, 0xbf];
+        assert_eq!("+/+/", base64_encode(&data));
+        assert_eq!("-_-_", base64_url_encode(&data));
+        assert_eq!(&data[..], &base64_url_decode("-_-_").unwrap()[..]);
+
+        assert_eq!("Zg", base64_url_encode(b"f"));
+        assert_eq!(&b"f"[..], &base64_url_decode("Zg==").unwrap()[..]);
+    }
+
+    #[test]
+    fn test_base32() {
+        for &(data, _, base32) in VECTORS.iter() {
+            assert_eq!(base32, base32_encode(data.as_bytes()));
+            assert_eq!(data.as_bytes(), &base32_decode(base32).unwrap()[..]);
+        }
+
+        assert_eq!(&b"foobar"[..], &base32_decode("mzxw6ytboi").unwrap()[..]);
+    }
+
+    #[test]
+    fn test_whitespace() {
+        assert_eq!(
+            &b"foobar"[..],
+            &base64_decode("Zm9v\n  YmFy\r\n").unwrap()[..]
+        );
+        assert_eq!(
+            &b"foobar"[..],
+            &base32_decode("MZXW 6YTB\nOI======\n").unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Err(Error::InvalidCharacter('*')), base64_decode("Zm9*"));
+        assert_eq!(Err(Error::InvalidCharacter('-')), base64_decode("Zm9-"));
+        assert_eq!(Err(Error::InvalidCharacter('1')), base32_decode("MZXW1"));
+        assert_eq!(Err(Error::InvalidLength), base64_decode("Zm9vY"));
+        assert_eq!(Err(Error::InvalidLength), base32_decode("MZX"));
+    }
+
+    #[test]
+    fn test_armor() {
+        let data: Vec<u8> = (0..100).collect();
+        let armored = armor("PRESENT MESSAGE", &data);
+
+        let lines: Vec<&str> = armored.lines().collect();
+        assert_eq!("-----BEGIN PRESENT MESSAGE-----", lines[0]);
+        assert_eq!(64, lines[1].len());
+        assert_eq!("-----END PRESENT MESSAGE-----", lines[lines.len() - 1]);
+
+        let pasted = format!("Ciphertext below:\r\n\r\n  {}\n\nThanks", armored);
+        assert_eq!(
+            ("PRESENT MESSAGE".to_string(), data.clone()),
+            dearmor(&pasted).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_armor_headers() {
+        let armored = armor_with_headers("PRESENT KEY", &[("Key-Size", "80")], &[0u8; 10]);
+        assert_eq!(
+            "-----BEGIN PRESENT KEY-----\nKey-Size: 80\n\nAAAAAAAAAAAAAA==\n-----END PRESENT KEY-----\n",
+            armored
+        );
+
+        let dearmored = dearmor_with_headers(&armored).unwrap();
+        assert_eq!("PRESENT KEY", dearmored.label);
+        assert_eq!(
+            vec![("Key-Size".to_string(), "80".to_string())],
+            dearmored.headers
+        );
+        assert_eq!(vec![0u8; 10], dearmored.data);
+
+        assert_eq!(
+            ("PRESENT KEY".to_string(), vec![0u8; 10]),
+            dearmor(&armored).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dearmor_invalid() {
+        assert_eq!(Err(Error::InvalidArmor), dearmor("Zm9vYmFy"));
+        assert_eq!(
+            Err(Error::InvalidArmor),
+            dearmor("-----BEGIN PRESENT KEY-----\nZm9vYmFy\n")
+        );
+        assert_eq!(
+            Err(Error::InvalidArmor),
+            dearmor("-----BEGIN PRESENT KEY-----\nZm9vYmFy\n-----END PRESENT MESSAGE-----\n")
+        );
+    }
+}
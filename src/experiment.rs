@@ -0,0 +1,212 @@
+use getrandom;
+use rayon::prelude::*;
+
+use super::container::KeySize;
+use super::reduced::{self, Rounds};
+use super::trail::Kind;
+use super::{present128, present80, BlockCipher};
+
+// Runs a differential or linear approximation through the reduced-round cipher with random
+// keys and plaintexts. Without whitening, the input and output line up with a trail's first
+// round input and its output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Experiment {
+    pub kind: Kind,
+    pub key_size: KeySize,
+    pub rounds: Rounds,
+    pub input: u64,
+    pub output: u64,
+    pub num_keys: usize,
+    pub samples_per_key: u64,
+    pub seed: u64,
+}
+
+// The probability of the differential, or the average absolute correlation of the linear
+// approximation, since its sign depends on the key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub samples: u64,
+    pub probability: f64,
+}
+
+impl Estimate {
+    pub fn weight(&self) -> f64 {
+        -self.probability.log2()
+    }
+}
+
+// SplitMix64, which is fast and good enough for sampling, unlike the CSPRNG the keys for actual
+// encryption come from.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    // Each stream of a seed gets its own generator, so that work can be split between threads.
+    pub(crate) fn new(seed: u64, stream: u64) -> Rng {
+        Rng(seed ^ stream.wrapping_mul(0xd1b54a32d192ed03))
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let n = chunk.len();
+            chunk.copy_from_slice(&self.next().to_be_bytes()[..n]);
+        }
+    }
+}
+
+pub(crate) fn random_cipher(
+    rng: &mut Rng,
+    key_size: KeySize,
+    rounds: Rounds,
+) -> Result<reduced::Cipher, reduced::Error> {
+    match key_size {
+        KeySize::Key80 => {
+            let mut bytes = [0u8; present80::KEY_LENGTH_IN_BYTES];
+            rng.fill(&mut bytes);
+            reduced::Cipher::new80(present80::Key::new(&bytes), rounds)
+        }
+        KeySize::Key128 => {
+            let mut bytes = [0u8; present128::KEY_LENGTH_IN_BYTES];
+            rng.fill(&mut bytes);
+            reduced::Cipher::new128(present128::Key::new(&bytes), rounds)
+        }
+    }
+}
+
+fn parity(x: u64) -> bool {
+    x.count_ones() % 2 == 1
+}
+
+impl Experiment {
+    pub fn new(kind: Kind, num_rounds: usize, input: u64, output: u64) -> Experiment {
+        let mut seed = [0u8; 8];
+        getrandom::getrandom(&mut seed).expect("error seeding experiment");
+
+        Experiment {
+            kind,
+            key_size: KeySize::Key80,
+            rounds: Rounds {
+                first: 1,
+                count: num_rounds,
+                whitening: false,
+            },
+            input,
+            output,
+            num_keys: 64,
+            samples_per_key: 1 << 16,
+            seed: u64::from_be_bytes(seed),
+        }
+    }
+
+    // The number of samples for one key that follow the differential or approximation.
+    fn count(&self, key_index: usize) -> Result<u64, reduced::Error> {
+        let mut rng = Rng::new(self.seed, key_index as u64);
+        let cipher = random_cipher(&mut rng, self.key_size, self.rounds)?;
+
+        let mut hits = 0;
+        for _ in 0..self.samples_per_key {
+            let plaintext = rng.next();
+            let ciphertext = cipher.encrypt_state(plaintext);
+            let hit = match self.kind {
+                Kind::Differential => {
+                    let other = cipher.encrypt_state(plaintext ^ self.input);
+                    ciphertext ^ other == self.output
+                }
+                Kind::Linear => parity(plaintext & self.input) == parity(ciphertext & self.output),
+            };
+            hits += hit as u64;
+        }
+        Ok(hits)
+    }
+
+    // Keys are spread over threads, so the same seed gives the same estimate however they are
+    // scheduled.
+    pub fn run(&self) -> Result<Estimate, reduced::Error> {
+        let counts = (0..self.num_keys)
+            .into_par_iter()
+            .map(|key_index| self.count(key_index))
+            .collect::<Result<Vec<u64>, reduced::Error>>()?;
+
+        let samples = self.num_keys as u64 * self.samples_per_key;
+        let probability = match self.kind {
+            Kind::Differential => counts.iter().sum::<u64>() as f64 / samples as f64,
+            Kind::Linear => {
+                let n = self.samples_per_key as f64;
+                let total: f64 = counts
+                    .iter()
+                    .map(|&hits| (2.0 * hits as f64 / n - 1.0).abs())
+                    .sum();
+                total / self.num_keys as f64
+            }
+        };
+        Ok(Estimate {
+            samples,
+            probability,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::trail;
+    use super::*;
+
+    fn experiment(trail: &trail::Trail) -> Experiment {
+        let mut experiment = Experiment::new(
+            trail.kind,
+            trail.rounds.len(),
+            trail.rounds[0].input,
+            trail.output(),
+        );
+        experiment.num_keys = 8;
+        experiment.samples_per_key = 1 << 14;
+        experiment.seed = 42;
+        experiment
+    }
+
+    #[test]
+    fn test_differential() {
+        let trail = trail::best_differential_trail(2);
+        let estimate = experiment(&trail).run().unwrap();
+        assert_eq!(8 << 14, estimate.samples);
+        assert!((estimate.weight() - trail.weight()).abs() < 0.5);
+
+        // A permutation never maps a nonzero difference to zero.
+        let mut experiment = experiment(&trail);
+        experiment.output = 0;
+        assert_eq!(0.0, experiment.run().unwrap().probability);
+    }
+
+    #[test]
+    fn test_linear() {
+        let trail = trail::best_linear_trail(2);
+        for &key_size in [KeySize::Key80, KeySize::Key128].iter() {
+            let mut experiment = experiment(&trail);
+            experiment.key_size = key_size;
+            let estimate = experiment.run().unwrap();
+            assert!((estimate.weight() - trail.weight()).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let trail = trail::best_differential_trail(1);
+        let experiment = experiment(&trail);
+        assert_eq!(experiment.run().unwrap(), experiment.run().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_rounds() {
+        let trail = trail::best_differential_trail(1);
+        let mut experiment = experiment(&trail);
+        experiment.rounds.count = 40;
+        assert!(experiment.run().is_err());
+    }
+}
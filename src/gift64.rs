@@ -0,0 +1,333 @@
+use std::ops;
+
+use rayon::prelude::*;
+
+use super::spn::{KeySchedule, Spn};
+use super::ExpandedKey;
+
+pub const KEY_LENGTH_IN_BYTES: usize = 16;
+pub const NUM_ROUNDS: usize = 28;
+pub const S: [u8; 16] = [1, 0xA, 4, 0xC, 6, 0xF, 3, 9, 2, 0xD, 0xB, 7, 5, 0, 8, 0xE];
+pub const P: [u8; 64] = [
+    0, 17, 34, 51, 48, 1, 18, 35, 32, 49, 2, 19, 16, 33, 50, 3, 4, 21, 38, 55, 52, 5, 22, 39, 36,
+    53, 6, 23, 20, 37, 54, 7, 8, 25, 42, 59, 56, 9, 26, 43, 40, 57, 10, 27, 24, 41, 58, 11, 12, 29,
+    46, 63, 60, 13, 30, 47, 44, 61, 14, 31, 28, 45, 62, 15,
+];
+pub const GIFT: Spn = match Spn::new(S, P) {
+    Ok(spn) => spn,
+    Err(_) => panic!("GIFT's S-box and permutation must be invertible"),
+};
+
+// GIFT adds each round key after the permutation and has no final whitening. Prepending a zero
+// key turns that into PRESENT's round structure, so the rounds run on the SPN engine unchanged.
+#[derive(Clone)]
+struct RoundKeys([u64; NUM_ROUNDS + 1]);
+
+#[derive(Clone)]
+pub struct Key {
+    bytes: [u8; KEY_LENGTH_IN_BYTES],
+}
+
+#[derive(Clone)]
+pub struct Cipher {
+    round_keys: RoundKeys,
+}
+
+// The key as eight 16-bit words, with k0 the least significant.
+struct KeyState([u16; 8]);
+
+impl Key {
+    pub fn new(bytes: &[u8]) -> Key {
+        let mut b = [0u8; KEY_LENGTH_IN_BYTES];
+        let len = bytes.len().min(KEY_LENGTH_IN_BYTES);
+        b[..len].copy_from_slice(&bytes[..len]);
+
+        Key { bytes: b }
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        super::zeroize(&mut self.bytes);
+    }
+}
+
+impl ops::Deref for RoundKeys {
+    type Target = [u64; NUM_ROUNDS + 1];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl ops::DerefMut for RoundKeys {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for RoundKeys {
+    fn drop(&mut self) {
+        super::zeroize(&mut self.0);
+    }
+}
+
+impl Drop for KeyState {
+    fn drop(&mut self) {
+        super::zeroize(&mut self.0);
+    }
+}
+
+impl Cipher {
+    pub fn new(key: Key) -> Cipher {
+        Cipher {
+            round_keys: generate_round_keys(key),
+        }
+    }
+
+    pub fn encrypt_traced(&self, state: u64) -> super::trace::Trace {
+        super::trace::encrypt_traced(&GIFT, state, &self.round_keys[..])
+    }
+}
+
+impl super::BlockCipher for Cipher {
+    fn encrypt_state(&self, state: u64) -> u64 {
+        encrypt(state, &self.round_keys)
+    }
+
+    fn decrypt_state(&self, state: u64) -> u64 {
+        decrypt(state, &self.round_keys)
+    }
+}
+
+impl From<Key> for KeyState {
+    fn from(key: Key) -> Self {
+        let mut words = [0u16; 8];
+        for (i, word) in words.iter_mut().enumerate() {
+            let j = KEY_LENGTH_IN_BYTES - 2 * (i + 1);
+            *word = u16::from_be_bytes([key.bytes[j], key.bytes[j + 1]]);
+        }
+
+        KeyState(words)
+    }
+}
+
+impl KeyState {
+    // U = k1 goes to bit 1 and V = k0 to bit 0 of every nibble.
+    fn round_key(&self) -> u64 {
+        let (u, v) = (self.0[1] as u64, self.0[0] as u64);
+        let mut round_key = 0u64;
+        for i in 0..16 {
+            round_key |= ((u >> i) & 1) << (4 * i + 1);
+            round_key |= ((v >> i) & 1) << (4 * i);
+        }
+        round_key
+    }
+
+    fn update(&mut self) {
+        let k = self.0;
+        self.0 = [
+            k[2],
+            k[3],
+            k[4],
+            k[5],
+            k[6],
+            k[7],
+            k[0].rotate_right(12),
+            k[1].rotate_right(2),
+        ];
+    }
+}
+
+// The 6-bit LFSR of round constants, which starts from zero and steps before every round.
+fn next_round_constant(c: u64) -> u64 {
+    ((c << 1) & 0x3f) | (((c >> 5) ^ (c >> 4) ^ 1) & 1)
+}
+
+// The round constant goes to bits 3, 7, ..., 23 and a single 1 to bit 63.
+fn add_round_constant(round_key: u64, c: u64) -> u64 {
+    let mut round_key = round_key ^ 1 << 63;
+    for i in 0..6 {
+        round_key ^= ((c >> i) & 1) << (4 * i + 3);
+    }
+    round_key
+}
+
+fn expand_key(key: Key, num_rounds: usize) -> ExpandedKey {
+    let mut round_keys = Vec::with_capacity(num_rounds + 1);
+    round_keys.push(0);
+
+    let mut key_state = KeyState::from(key);
+    let mut c = 0;
+    for _ in 0..num_rounds {
+        c = next_round_constant(c);
+        round_keys.push(add_round_constant(key_state.round_key(), c));
+        key_state.update();
+    }
+    ExpandedKey::from(round_keys)
+}
+
+fn generate_round_keys(key: Key) -> RoundKeys {
+    let mut round_keys = RoundKeys([0u64; NUM_ROUNDS + 1]);
+    round_keys.copy_from_slice(&expand_key(key, NUM_ROUNDS));
+    round_keys
+}
+
+impl KeySchedule for Key {
+    fn round_keys(&self, num_rounds: usize) -> ExpandedKey {
+        expand_key(self.clone(), num_rounds)
+    }
+}
+
+fn decrypt(state: u64, round_keys: &RoundKeys) -> u64 {
+    GIFT.decrypt(state, &round_keys[..])
+}
+
+fn encrypt(state: u64, round_keys: &RoundKeys) -> u64 {
+    GIFT.encrypt(state, &round_keys[..])
+}
+
+fn join(blocks: Vec<[u8; super::BLOCK_SIZE_IN_BYTES]>) -> Vec<u8> {
+    let mut joined: Vec<u8> = Vec::with_capacity(blocks.len() * super::BLOCK_SIZE_IN_BYTES);
+    for block in blocks.iter() {
+        joined.extend(block.iter());
+    }
+    joined
+}
+
+pub fn ecb_encrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
+
+    join(
+        data.chunks(super::BLOCK_SIZE_IN_BYTES)
+            .map(super::bytes_to_state)
+            .map(|state| encrypt(state, &round_keys))
+            .map(super::state_to_bytes)
+            .collect(),
+    )
+}
+
+pub fn ecb_decrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
+
+    join(
+        data.chunks(super::BLOCK_SIZE_IN_BYTES)
+            .map(super::bytes_to_state)
+            .map(|state| decrypt(state, &round_keys))
+            .map(super::state_to_bytes)
+            .collect(),
+    )
+}
+
+pub fn par_ecb_encrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
+
+    join(
+        data.par_chunks(super::BLOCK_SIZE_IN_BYTES)
+            .map(super::bytes_to_state)
+            .map(|state| encrypt(state, &round_keys))
+            .map(super::state_to_bytes)
+            .collect(),
+    )
+}
+
+pub fn par_ecb_decrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
+
+    join(
+        data.par_chunks(super::BLOCK_SIZE_IN_BYTES)
+            .map(super::bytes_to_state)
+            .map(|state| decrypt(state, &round_keys))
+            .map(super::state_to_bytes)
+            .collect(),
+    )
+}
+
+pub fn encrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
+    let state = super::bytes_to_state(data);
+    let round_keys = generate_round_keys(key);
+
+    super::state_to_bytes(encrypt(state, &round_keys))
+}
+
+pub fn encrypt_traced(data: &[u8], key: Key) -> super::trace::Trace {
+    Cipher::new(key).encrypt_traced(super::bytes_to_state(data))
+}
+
+pub fn decrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
+    let state = super::bytes_to_state(data);
+    let round_keys = generate_round_keys(key);
+
+    super::state_to_bytes(decrypt(state, &round_keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::super::{spn, BlockCipher};
+    use super::*;
+
+    #[test]
+    fn test_key_state_from_key() {
+        let mut bytes = [0u8; KEY_LENGTH_IN_BYTES];
+        bytes[0] = 0x12;
+        bytes[15] = 0x34;
+        let key_state = KeyState::from(Key::new(&bytes));
+        assert_eq!([0x34, 0, 0, 0, 0, 0, 0, 0x1200], key_state.0);
+    }
+
+    #[test]
+    fn test_round_key() {
+        let key_state = KeyState([0xffff, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(0x1111111111111111, key_state.round_key());
+        let key_state = KeyState([0, 0x8000, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(1 << 61, key_state.round_key());
+    }
+
+    #[test]
+    fn test_update() {
+        let mut key_state = KeyState([1, 1, 2, 3, 4, 5, 6, 7]);
+        key_state.update();
+        assert_eq!([2, 3, 4, 5, 6, 7, 0x10, 0x4000], key_state.0);
+    }
+
+    #[test]
+    fn test_round_constants() {
+        let expected = [
+            0x01, 0x03, 0x07, 0x0F, 0x1F, 0x3E, 0x3D, 0x3B, 0x37, 0x2F, 0x1E, 0x3C, 0x39, 0x33,
+            0x27, 0x0E, 0x1D, 0x3A, 0x35, 0x2B, 0x16, 0x2C, 0x18, 0x30, 0x21, 0x02, 0x05, 0x0B,
+        ];
+        let mut c = 0;
+        for &expected in expected.iter() {
+            c = next_round_constant(c);
+            assert_eq!(expected, c);
+        }
+
+        assert_eq!(1 << 63 | 1 << 23 | 1 << 3, add_round_constant(0, 0x21));
+    }
+
+    #[test]
+    fn test_key_schedule() {
+        let key = Key::new(&[0x5a; 16]);
+        let cipher = spn::Cipher::new(GIFT, &key, NUM_ROUNDS);
+        assert_eq!(
+            Cipher::new(key).encrypt_state(0x0123456789abcdef),
+            cipher.encrypt_state(0x0123456789abcdef)
+        );
+    }
+
+    #[test]
+    fn test_zeroize_on_drop() {
+        let mut key = mem::ManuallyDrop::new(Key::new(&[0xff; 16]));
+        let mut round_keys = mem::ManuallyDrop::new(generate_round_keys(Key::new(&[0xff; 16])));
+        assert!(round_keys.iter().skip(1).all(|&round_key| round_key != 0));
+
+        unsafe {
+            mem::ManuallyDrop::drop(&mut key);
+            mem::ManuallyDrop::drop(&mut round_keys);
+        }
+        assert_eq!([0u8; KEY_LENGTH_IN_BYTES], key.bytes);
+        assert_eq!([0u64; NUM_ROUNDS + 1], round_keys.0);
+    }
+}
@@ -0,0 +1,262 @@
+use std::io;
+
+use super::present128;
+use super::present80;
+use super::BlockCipher;
+
+// Hash constructions from Bogdanov et al., "Hash Functions and RFID Tags: Mind the Gap"
+// (CHES 2008). All chaining values start at zero.
+pub const DM_PRESENT_DIGEST_SIZE_IN_BYTES: usize = 8;
+pub const H_PRESENT_DIGEST_SIZE_IN_BYTES: usize = 16;
+
+// Any non-zero constant gives the Hirose construction its security bound.
+const HIROSE_CONSTANT: u64 = 0xffffffffffffffff;
+
+const LENGTH_SIZE_IN_BYTES: usize = 8;
+
+pub trait Compression: Default {
+    const BLOCK_SIZE_IN_BYTES: usize;
+
+    fn compress(&mut self, block: &[u8]);
+    fn digest(&self) -> Vec<u8>;
+}
+
+#[derive(Default)]
+pub struct DaviesMeyer80 {
+    h: u64,
+}
+
+#[derive(Default)]
+pub struct DaviesMeyer128 {
+    h: u64,
+}
+
+#[derive(Default)]
+pub struct Hirose128 {
+    g: u64,
+    h: u64,
+}
+
+impl Compression for DaviesMeyer80 {
+    const BLOCK_SIZE_IN_BYTES: usize = present80::KEY_LENGTH_IN_BYTES;
+
+    fn compress(&mut self, block: &[u8]) {
+        let cipher = present80::Cipher::new(present80::Key::new(block));
+        self.h ^= cipher.encrypt_state(self.h);
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        super::state_to_bytes(self.h).to_vec()
+    }
+}
+
+impl Compression for DaviesMeyer128 {
+    const BLOCK_SIZE_IN_BYTES: usize = present128::KEY_LENGTH_IN_BYTES;
+
+    fn compress(&mut self, block: &[u8]) {
+        let cipher = present128::Cipher::new(present128::Key::new(block));
+        self.h ^= cipher.encrypt_state(self.h);
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        super::state_to_bytes(self.h).to_vec()
+    }
+}
+
+impl Compression for Hirose128 {
+    const BLOCK_SIZE_IN_BYTES: usize = super::BLOCK_SIZE_IN_BYTES;
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut key = [0u8; present128::KEY_LENGTH_IN_BYTES];
+        key[..super::BLOCK_SIZE_IN_BYTES].copy_from_slice(&super::state_to_bytes(self.h));
+        key[super::BLOCK_SIZE_IN_BYTES..].copy_from_slice(block);
+
+        let cipher = present128::Cipher::new(present128::Key::new(&key));
+        let g = cipher.encrypt_state(self.g) ^ self.g;
+        let h = cipher.encrypt_state(self.g ^ HIROSE_CONSTANT) ^ self.g ^ HIROSE_CONSTANT;
+
+        self.g = g;
+        self.h = h;
+    }
+
+    fn digest(&self) -> Vec<u8> {
+        let mut digest = Vec::with_capacity(H_PRESENT_DIGEST_SIZE_IN_BYTES);
+        digest.extend(super::state_to_bytes(self.g).iter());
+        digest.extend(super::state_to_bytes(self.h).iter());
+        digest
+    }
+}
+
+#[derive(Default)]
+pub struct Hasher<C: Compression> {
+    compression: C,
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+pub type DmPresent80 = Hasher<DaviesMeyer80>;
+pub type DmPresent128 = Hasher<DaviesMeyer128>;
+pub type HPresent128 = Hasher<Hirose128>;
+
+impl<C: Compression> Hasher<C> {
+    pub fn new() -> Hasher<C> {
+        Hasher {
+            compression: C::default(),
+            buffer: Vec::with_capacity(C::BLOCK_SIZE_IN_BYTES),
+            length: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+
+        let mut data = data;
+        if !self.buffer.is_empty() {
+            let needed = C::BLOCK_SIZE_IN_BYTES - self.buffer.len();
+            if data.len() < needed {
+                self.buffer.extend_from_slice(data);
+                return;
+            }
+
+            self.buffer.extend_from_slice(&data[..needed]);
+            self.compression.compress(&self.buffer);
+            self.buffer.clear();
+            data = &data[needed..];
+        }
+
+        for block in data.chunks(C::BLOCK_SIZE_IN_BYTES) {
+            if block.len() < C::BLOCK_SIZE_IN_BYTES {
+                self.buffer.extend_from_slice(block);
+                break;
+            }
+            self.compression.compress(block);
+        }
+    }
+
+    // Merkle-Damgard strengthening: a single one bit, zeroes, then the message length in bits.
+    pub fn finalize(mut self) -> Vec<u8> {
+        let bit_length = self.length.wrapping_mul(8);
+
+        let mut padding = vec![0x80u8];
+        let unpadded = self.buffer.len() + 1 + LENGTH_SIZE_IN_BYTES;
+        let remainder = unpadded % C::BLOCK_SIZE_IN_BYTES;
+        if remainder != 0 {
+            padding.resize(1 + C::BLOCK_SIZE_IN_BYTES - remainder, 0);
+        }
+        for i in 0..LENGTH_SIZE_IN_BYTES {
+            padding.push((bit_length >> ((7 - i) * 8)) as u8);
+        }
+
+        let mut buffer = Vec::new();
+        buffer.append(&mut self.buffer);
+        buffer.extend(padding);
+        for block in buffer.chunks(C::BLOCK_SIZE_IN_BYTES) {
+            self.compression.compress(block);
+        }
+
+        self.compression.digest()
+    }
+}
+
+impl<C: Compression> io::Write for Hasher<C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn dm_present80(data: &[u8]) -> Vec<u8> {
+    let mut hasher = DmPresent80::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+pub fn dm_present128(data: &[u8]) -> Vec<u8> {
+    let mut hasher = DmPresent128::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+pub fn h_present128(data: &[u8]) -> Vec<u8> {
+    let mut hasher = HPresent128::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use super::*;
+
+    // The compression function vectors follow from the PRESENT test vectors in the
+    // original paper, since the message block is used as the cipher key.
+    #[test]
+    fn test_dm_present80_compress() {
+        let mut compression = DaviesMeyer80::default();
+        compression.compress(&[0u8; 10]);
+        assert_eq!("5579c1387b228445", hex::encode(compression.digest()));
+
+        let mut compression = DaviesMeyer80 { h: 0xffffffffffffffff };
+        compression.compress(&[0u8; 10]);
+        assert_eq!("5eed0038d097be84", hex::encode(compression.digest()));
+    }
+
+    #[test]
+    fn test_dm_present128_compress() {
+        let mut compression = DaviesMeyer128::default();
+        compression.compress(&[0u8; 16]);
+        assert_eq!("96db702a2e6900af", hex::encode(compression.digest()));
+    }
+
+    #[test]
+    fn test_h_present128_compress() {
+        let mut compression = Hirose128::default();
+        compression.compress(&[0u8; 8]);
+        assert_eq!(
+            "96db702a2e6900afc39fe61a1a122a9c",
+            hex::encode(compression.digest())
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        let data: Vec<u8> = (0..100).collect();
+
+        for split in [0, 1, 7, 8, 10, 15, 16, 17, 99, 100].iter() {
+            let mut hasher = DmPresent80::new();
+            hasher.update(&data[..*split]);
+            hasher.update(&data[*split..]);
+            assert_eq!(dm_present80(&data), hasher.finalize());
+
+            let mut hasher = DmPresent128::new();
+            hasher.update(&data[..*split]);
+            hasher.update(&data[*split..]);
+            assert_eq!(dm_present128(&data), hasher.finalize());
+
+            let mut hasher = HPresent128::new();
+            hasher.update(&data[..*split]);
+            hasher.update(&data[*split..]);
+            assert_eq!(h_present128(&data), hasher.finalize());
+        }
+    }
+
+    #[test]
+    fn test_strengthening() {
+        assert_ne!(dm_present80(&[]), dm_present80(&[0]));
+        assert_ne!(dm_present80(&[0; 2]), dm_present80(&[0; 10]));
+        assert_ne!(dm_present128(&[]), dm_present128(&[0; 16]));
+        assert_ne!(h_present128(&[0; 7]), h_present128(&[0; 8]));
+    }
+
+    #[test]
+    fn test_digest_sizes() {
+        assert_eq!(DM_PRESENT_DIGEST_SIZE_IN_BYTES, dm_present80(b"PRESENT").len());
+        assert_eq!(DM_PRESENT_DIGEST_SIZE_IN_BYTES, dm_present128(b"PRESENT").len());
+        assert_eq!(H_PRESENT_DIGEST_SIZE_IN_BYTES, h_present128(b"PRESENT").len());
+    }
+}
@@ -0,0 +1,141 @@
+use getrandom;
+use rayon::prelude::*;
+
+use super::container::KeySize;
+use super::experiment::{self, Rng};
+use super::reduced::{self, Rounds};
+use super::{bytes_to_state, state_to_bytes, BlockCipher, BLOCK_SIZE_IN_BYTES};
+
+// Nibble 0 is the least significant, as in the S-box layer.
+pub fn nibble_mask(nibbles: &[usize]) -> u64 {
+    nibbles.iter().fold(0, |mask, &i| mask | 0xf << (4 * i))
+}
+
+// XORs the ciphertexts of the 2^k plaintexts that take every value on the k cube bits and
+// agree with constant everywhere else. A zero bit in the sum is balanced over the structure.
+pub fn cube_sum<C: BlockCipher>(cipher: &C, cube: u64, constant: u64) -> u64 {
+    let constant = constant & !cube;
+    let mut sum = 0;
+    let mut bits = 0u64;
+    loop {
+        sum ^= cipher.encrypt_state(constant | bits);
+        // Steps through every subset of the cube bits in turn.
+        bits = bits.wrapping_sub(cube) & cube;
+        if bits == 0 {
+            return sum;
+        }
+    }
+}
+
+pub fn cube_sum_block<C: BlockCipher>(
+    cipher: &C,
+    cube: &[u8],
+    constant: &[u8],
+) -> [u8; BLOCK_SIZE_IN_BYTES] {
+    state_to_bytes(cube_sum(
+        cipher,
+        bytes_to_state(cube),
+        bytes_to_state(constant),
+    ))
+}
+
+// Evaluates an integral distinguisher by summing the structure over random keys and constants.
+// An output bit is only reported balanced if it was balanced every time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Integral {
+    pub key_size: KeySize,
+    pub rounds: Rounds,
+    pub active: u64,
+    pub trials: usize,
+    pub seed: u64,
+}
+
+impl Integral {
+    pub fn new(num_rounds: usize, active: u64) -> Integral {
+        let mut seed = [0u8; 8];
+        getrandom::getrandom(&mut seed).expect("error seeding integral");
+
+        Integral {
+            key_size: KeySize::Key80,
+            rounds: Rounds {
+                first: 1,
+                count: num_rounds,
+                whitening: false,
+            },
+            active,
+            trials: 16,
+            seed: u64::from_be_bytes(seed),
+        }
+    }
+
+    fn trial(&self, index: usize) -> Result<u64, reduced::Error> {
+        let mut rng = Rng::new(self.seed, index as u64);
+        let cipher = experiment::random_cipher(&mut rng, self.key_size, self.rounds)?;
+        Ok(cube_sum(&cipher, self.active, rng.next()))
+    }
+
+    // Returns the mask of balanced output bits.
+    pub fn run(&self) -> Result<u64, reduced::Error> {
+        let sums = (0..self.trials)
+            .into_par_iter()
+            .map(|index| self.trial(index))
+            .collect::<Result<Vec<u64>, reduced::Error>>()?;
+        Ok(!sums.iter().fold(0, |unbalanced, sum| unbalanced | sum))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::present80;
+    use super::*;
+
+    fn integral(num_rounds: usize, active: u64) -> Integral {
+        let mut integral = Integral::new(num_rounds, active);
+        integral.trials = 4;
+        integral.seed = 42;
+        integral
+    }
+
+    #[test]
+    fn test_nibble_mask() {
+        assert_eq!(0, nibble_mask(&[]));
+        assert_eq!(0xf00000000000000f, nibble_mask(&[0, 15]));
+    }
+
+    #[test]
+    fn test_cube_sum() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0x42; 10]));
+        assert_eq!(cipher.encrypt_state(5), cube_sum(&cipher, 0, 5));
+        assert_eq!(
+            cipher.encrypt_state(0x10) ^ cipher.encrypt_state(0x11),
+            cube_sum(&cipher, 1, 0x11)
+        );
+
+        let cube = [0, 0, 0, 0, 0, 0, 0x80, 0x01];
+        let constant = [0xff; 8];
+        assert_eq!(
+            state_to_bytes(cube_sum(&cipher, 0x8001, !0x8001)),
+            cube_sum_block(&cipher, &cube, &constant)
+        );
+    }
+
+    #[test]
+    fn test_integral() {
+        // One active nibble balances every bit for 4 rounds but no longer holds at 6, while four
+        // active nibbles last a round longer.
+        let active = nibble_mask(&[0]);
+        assert_eq!(!0, integral(4, active).run().unwrap());
+        assert_ne!(!0, integral(6, active).run().unwrap());
+        assert_eq!(!0, integral(5, nibble_mask(&[0, 1, 2, 3])).run().unwrap());
+
+        // The S-box has degree 3, so one round is linear over a cube of 4 bits in different
+        // nibbles, and its sum vanishes.
+        assert_eq!(!0, integral(1, 0x1111).run().unwrap());
+    }
+
+    #[test]
+    fn test_seed() {
+        let integral = integral(3, nibble_mask(&[3]));
+        assert_eq!(integral.run().unwrap(), integral.run().unwrap());
+    }
+}
@@ -0,0 +1,213 @@
+use std::error;
+use std::fmt;
+
+#[cfg(feature = "argon2")]
+use argon2::Argon2;
+
+use super::cmac::{Cmac, MAC_SIZE_IN_BYTES};
+use super::hash;
+use super::present128;
+use super::present80;
+
+pub const DEFAULT_ITERATIONS: u32 = 10000;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidIterations,
+    InvalidOutputLength,
+    #[cfg(feature = "argon2")]
+    Argon2(argon2::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidIterations => write!(f, "iteration count must be at least 1"),
+            Error::InvalidOutputLength => write!(f, "invalid output length"),
+            #[cfg(feature = "argon2")]
+            Error::Argon2(ref err) => write!(f, "argon2: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// PBKDF2 from RFC 8018 with PRESENT-128-CMAC as the PRF. CMAC needs a fixed-size key, so
+// the password is first compressed with H-PRESENT-128, in the spirit of RFC 4615.
+pub fn pbkdf2(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    output: &mut [u8],
+) -> Result<(), Error> {
+    if iterations == 0 {
+        return Err(Error::InvalidIterations);
+    }
+    if output.is_empty() {
+        return Err(Error::InvalidOutputLength);
+    }
+
+    let prf_key = present128::Key::new(&hash::h_present128(password));
+    let prf = present128::Cipher::new(prf_key);
+
+    for (i, chunk) in output.chunks_mut(MAC_SIZE_IN_BYTES).enumerate() {
+        let mut mac = Cmac::new(&prf);
+        mac.update(salt);
+        mac.update(&(i as u32 + 1).to_be_bytes());
+        let mut u = mac.finalize();
+        let mut t = u;
+
+        for _ in 1..iterations {
+            let mut mac = Cmac::new(&prf);
+            mac.update(&u);
+            u = mac.finalize();
+            for (x, y) in t.iter_mut().zip(u.iter()) {
+                *x ^= *y;
+            }
+        }
+
+        chunk.copy_from_slice(&t[..chunk.len()]);
+        super::zeroize(&mut t);
+        super::zeroize(&mut u);
+    }
+
+    Ok(())
+}
+
+pub fn pbkdf2_key80(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+) -> Result<present80::Key, Error> {
+    let mut bytes = [0u8; present80::KEY_LENGTH_IN_BYTES];
+    pbkdf2(password, salt, iterations, &mut bytes)?;
+    let key = present80::Key::new(&bytes);
+    super::zeroize(&mut bytes);
+    Ok(key)
+}
+
+pub fn pbkdf2_key128(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+) -> Result<present128::Key, Error> {
+    let mut bytes = [0u8; present128::KEY_LENGTH_IN_BYTES];
+    pbkdf2(password, salt, iterations, &mut bytes)?;
+    let key = present128::Key::new(&bytes);
+    super::zeroize(&mut bytes);
+    Ok(key)
+}
+
+// Argon2id with the argon2 crate's default parameters, for when PBKDF2's lack of memory
+// hardness is a concern. Salts must be at least 8 bytes.
+#[cfg(feature = "argon2")]
+pub fn argon2(password: &[u8], salt: &[u8], output: &mut [u8]) -> Result<(), Error> {
+    Argon2::default()
+        .hash_password_into(password, salt, output)
+        .map_err(Error::Argon2)
+}
+
+#[cfg(feature = "argon2")]
+pub fn argon2_key80(password: &[u8], salt: &[u8]) -> Result<present80::Key, Error> {
+    let mut bytes = [0u8; present80::KEY_LENGTH_IN_BYTES];
+    argon2(password, salt, &mut bytes)?;
+    let key = present80::Key::new(&bytes);
+    super::zeroize(&mut bytes);
+    Ok(key)
+}
+
+#[cfg(feature = "argon2")]
+pub fn argon2_key128(password: &[u8], salt: &[u8]) -> Result<present128::Key, Error> {
+    let mut bytes = [0u8; present128::KEY_LENGTH_IN_BYTES];
+    argon2(password, salt, &mut bytes)?;
+    let key = present128::Key::new(&bytes);
+    super::zeroize(&mut bytes);
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cmac;
+    use super::*;
+
+    #[test]
+    fn test_pbkdf2_single_iteration() {
+        let mut output = [0u8; 12];
+        pbkdf2(b"password", b"salt", 1, &mut output).unwrap();
+
+        let prf = present128::Cipher::new(present128::Key::new(&hash::h_present128(b"password")));
+        let t1 = cmac::cmac(&prf, b"salt\x00\x00\x00\x01");
+        let t2 = cmac::cmac(&prf, b"salt\x00\x00\x00\x02");
+        assert_eq!(&t1[..], &output[..8]);
+        assert_eq!(&t2[..4], &output[8..]);
+    }
+
+    #[test]
+    fn test_pbkdf2_iterations() {
+        let mut output = [0u8; 8];
+        pbkdf2(b"password", b"salt", 2, &mut output).unwrap();
+
+        let prf = present128::Cipher::new(present128::Key::new(&hash::h_present128(b"password")));
+        let u1 = cmac::cmac(&prf, b"salt\x00\x00\x00\x01");
+        let u2 = cmac::cmac(&prf, &u1);
+        let expected: Vec<u8> = u1.iter().zip(u2.iter()).map(|(x, y)| x ^ y).collect();
+        assert_eq!(&expected[..], &output[..]);
+    }
+
+    #[test]
+    fn test_pbkdf2_inputs() {
+        let derive = |password: &[u8], salt: &[u8], iterations| {
+            let mut output = [0u8; 16];
+            pbkdf2(password, salt, iterations, &mut output).unwrap();
+            output
+        };
+
+        let base = derive(b"password", b"salt", 10);
+        assert_eq!(base, derive(b"password", b"salt", 10));
+        assert_ne!(base, derive(b"Password", b"salt", 10));
+        assert_ne!(base, derive(b"password", b"Salt", 10));
+        assert_ne!(base, derive(b"password", b"salt", 11));
+    }
+
+    #[test]
+    fn test_pbkdf2_invalid() {
+        let mut output = [0u8; 8];
+        assert_eq!(
+            Err(Error::InvalidIterations),
+            pbkdf2(b"", b"", 0, &mut output)
+        );
+        assert_eq!(
+            Err(Error::InvalidOutputLength),
+            pbkdf2(b"", b"", 1, &mut [])
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2_keys() {
+        let key80 = pbkdf2_key80(b"password", b"salt", 1).unwrap();
+        let key128 = pbkdf2_key128(b"password", b"salt", 1).unwrap();
+
+        let mut output = [0u8; 16];
+        pbkdf2(b"password", b"salt", 1, &mut output).unwrap();
+        assert_eq!(
+            present80::encrypt_block(&[0u8; 8], present80::Key::new(&output[..10])),
+            present80::encrypt_block(&[0u8; 8], key80)
+        );
+        assert_eq!(
+            present128::encrypt_block(&[0u8; 8], present128::Key::new(&output)),
+            present128::encrypt_block(&[0u8; 8], key128)
+        );
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn test_argon2() {
+        let mut a = [0u8; 16];
+        let mut b = [0u8; 16];
+        argon2(b"password", b"saltsalt", &mut a).unwrap();
+        argon2(b"password", b"saltsalt", &mut b).unwrap();
+        assert_eq!(a, b);
+
+        assert!(argon2(b"password", b"salt", &mut a).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::error;
+use std::fmt;
+
+use getrandom;
+
+use super::container::KeySize;
+use super::encoding;
+use super::present128;
+use super::present80;
+
+pub const ARMOR_LABEL: &str = "PRESENT KEY";
+const KEY_SIZE_HEADER: &str = "Key-Size";
+
+#[derive(Debug)]
+pub enum Error {
+    Random(getrandom::Error),
+    Encoding(encoding::Error),
+    InvalidLabel(String),
+    KeySizeMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Random(ref err) => write!(f, "error generating key: {}", err),
+            Error::Encoding(ref err) => write!(f, "{}", err),
+            Error::InvalidLabel(ref label) => write!(f, "expected a key, found {}", label),
+            Error::KeySizeMismatch => write!(f, "key does not match its Key-Size header"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<encoding::Error> for Error {
+    fn from(err: encoding::Error) -> Error {
+        Error::Encoding(err)
+    }
+}
+
+fn key_length_in_bytes(key_size: KeySize) -> usize {
+    match key_size {
+        KeySize::Key80 => present80::KEY_LENGTH_IN_BYTES,
+        KeySize::Key128 => present128::KEY_LENGTH_IN_BYTES,
+    }
+}
+
+// Keys come straight from the operating system's CSPRNG.
+pub fn generate(key_size: KeySize) -> Result<Vec<u8>, Error> {
+    let mut key_bytes = vec![0u8; key_length_in_bytes(key_size)];
+    getrandom::getrandom(&mut key_bytes).map_err(Error::Random)?;
+    Ok(key_bytes)
+}
+
+pub fn generate_key80() -> Result<present80::Key, Error> {
+    let mut key_bytes = generate(KeySize::Key80)?;
+    let key = present80::Key::new(&key_bytes);
+    super::zeroize(&mut key_bytes);
+    Ok(key)
+}
+
+pub fn generate_key128() -> Result<present128::Key, Error> {
+    let mut key_bytes = generate(KeySize::Key128)?;
+    let key = present128::Key::new(&key_bytes);
+    super::zeroize(&mut key_bytes);
+    Ok(key)
+}
+
+pub fn armor(key_size: KeySize, key_bytes: &[u8]) -> String {
+    let bits = key_size.bits().to_string();
+    encoding::armor_with_headers(ARMOR_LABEL, &[(KEY_SIZE_HEADER, &bits)], key_bytes)
+}
+
+// The Key-Size header is optional, but must match the key if it is there.
+pub fn dearmor(armored: &str) -> Result<Vec<u8>, Error> {
+    let armored = encoding::dearmor_with_headers(armored)?;
+    if armored.label != ARMOR_LABEL {
+        return Err(Error::InvalidLabel(armored.label));
+    }
+
+    let bits = (armored.data.len() * 8).to_string();
+    for (name, value) in armored.headers.iter() {
+        if name == KEY_SIZE_HEADER && *value != bits {
+            return Err(Error::KeySizeMismatch);
+        }
+    }
+    Ok(armored.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        assert_eq!(10, generate(KeySize::Key80).unwrap().len());
+        assert_eq!(16, generate(KeySize::Key128).unwrap().len());
+        assert_ne!(
+            generate(KeySize::Key128).unwrap(),
+            generate(KeySize::Key128).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_generate_keys() {
+        let plaintext = [0u8; 8];
+        assert_ne!(
+            present80::encrypt_block(&plaintext, generate_key80().unwrap()),
+            present80::encrypt_block(&plaintext, generate_key80().unwrap())
+        );
+        assert_ne!(
+            present128::encrypt_block(&plaintext, generate_key128().unwrap()),
+            present128::encrypt_block(&plaintext, generate_key128().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_armor() {
+        let key_bytes = generate(KeySize::Key128).unwrap();
+        let armored = armor(KeySize::Key128, &key_bytes);
+        assert!(armored.contains("\nKey-Size: 128\n"));
+        assert_eq!(key_bytes, dearmor(&armored).unwrap());
+    }
+
+    #[test]
+    fn test_dearmor_invalid() {
+        let armored = armor(KeySize::Key80, &[0u8; 16]);
+        match dearmor(&armored) {
+            Err(Error::KeySizeMismatch) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let armored = encoding::armor("PRESENT MESSAGE", &[0u8; 16]);
+        match dearmor(&armored) {
+            Err(Error::InvalidLabel(ref label)) if label == "PRESENT MESSAGE" => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -1,22 +1,103 @@
+#[cfg(feature = "argon2")]
+extern crate argon2;
+#[cfg(feature = "rand_core")]
+extern crate rand_core;
+extern crate getrandom;
 extern crate rayon;
 
+use std::ops;
+use std::ptr;
+use std::sync::atomic;
+
 pub mod present80;
 pub mod present128;
+pub mod analysis;
+pub mod attack;
+pub mod cmac;
+pub mod container;
+pub mod drbg;
+pub mod eax;
+pub mod encoding;
+pub mod experiment;
+pub mod gift64;
+pub mod hash;
+pub mod integral;
+pub mod kdf;
+pub mod keygen;
+pub mod modes;
+pub mod reduced;
+pub mod spn;
+pub mod trace;
+pub mod trail;
+pub mod wrap;
 
 pub const BLOCK_SIZE_IN_BYTES: usize = 8;
-pub(crate) const NUM_ROUNDS: usize = 31;
-pub(crate) const S: [u8; 16] = [0xC, 5, 6, 0xB, 9, 0, 0xA, 0xD, 3, 0xE, 0xF, 8, 4, 7, 1, 2];
-pub(crate) const S_INV: [u8; 16] = [5, 0xE, 0xF, 8, 0xC, 1, 2, 0xD, 0xB, 4, 6, 3, 0, 7, 9, 0xA];
+pub const NUM_ROUNDS: usize = 31;
+pub const S: [u8; 16] = [0xC, 5, 6, 0xB, 9, 0, 0xA, 0xD, 3, 0xE, 0xF, 8, 4, 7, 1, 2];
 pub(crate) const P: [u8; 64] = [
     0, 16, 32, 48, 1, 17, 33, 49, 2, 18, 34, 50, 3, 19, 35, 51, 4, 20, 36, 52, 5, 21, 37, 53, 6,
     22, 38, 54, 7, 23, 39, 55, 8, 24, 40, 56, 9, 25, 41, 57, 10, 26, 42, 58, 11, 27, 43, 59, 12,
     28, 44, 60, 13, 29, 45, 61, 14, 30, 46, 62, 15, 31, 47, 63,
 ];
-pub(crate) const P_INV: [u8; 64] = [
-    0, 4, 8, 12, 16, 20, 24, 28, 32, 36, 40, 44, 48, 52, 56, 60, 1, 5, 9, 13, 17, 21, 25, 29, 33,
-    37, 41, 45, 49, 53, 57, 61, 2, 6, 10, 14, 18, 22, 26, 30, 34, 38, 42, 46, 50, 54, 58, 62, 3, 7,
-    11, 15, 19, 23, 27, 31, 35, 39, 43, 47, 51, 55, 59, 63,
-];
+// The inverse tables are derived from S and P, so tables that do not invert fail to compile.
+pub const PRESENT: spn::Spn = match spn::Spn::new(S, P) {
+    Ok(spn) => spn,
+    Err(_) => panic!("PRESENT's S-box and permutation must be invertible"),
+};
+
+pub trait BlockCipher {
+    fn encrypt_state(&self, state: u64) -> u64;
+    fn decrypt_state(&self, state: u64) -> u64;
+
+    fn encrypt_block(&self, data: &[u8]) -> [u8; BLOCK_SIZE_IN_BYTES] {
+        state_to_bytes(self.encrypt_state(bytes_to_state(data)))
+    }
+
+    fn decrypt_block(&self, data: &[u8]) -> [u8; BLOCK_SIZE_IN_BYTES] {
+        state_to_bytes(self.decrypt_state(bytes_to_state(data)))
+    }
+}
+
+impl<C: BlockCipher> BlockCipher for &C {
+    fn encrypt_state(&self, state: u64) -> u64 {
+        (*self).encrypt_state(state)
+    }
+
+    fn decrypt_state(&self, state: u64) -> u64 {
+        (*self).decrypt_state(state)
+    }
+}
+
+// Round keys for any number of rounds, wiped when dropped.
+pub struct ExpandedKey(Vec<u64>);
+
+impl From<Vec<u64>> for ExpandedKey {
+    fn from(round_keys: Vec<u64>) -> Self {
+        ExpandedKey(round_keys)
+    }
+}
+
+impl ops::Deref for ExpandedKey {
+    type Target = [u64];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for ExpandedKey {
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+// Volatile writes are never optimised away, even when the memory is about to be freed.
+pub fn zeroize<T: Copy + Default>(data: &mut [T]) {
+    for x in data.iter_mut() {
+        unsafe { ptr::write_volatile(x, T::default()) };
+    }
+    atomic::compiler_fence(atomic::Ordering::SeqCst);
+}
 
 pub(crate) fn pad(data: &[u8]) -> Vec<u8> {
     let num_blocks = match (data.len() / 8, data.len() % 8) {
@@ -31,77 +112,51 @@ pub(crate) fn pad(data: &[u8]) -> Vec<u8> {
 }
 
 pub(crate) fn s_box_layer(state: u64) -> u64 {
-    let mut new_state = 0u64;
-    for i in 0..16 {
-        let shift = i * 4;
-        let mask = 0xf << shift;
-        let x = (state & mask) >> shift;
-        let y = S[x as usize] as u64;
-        let z = y << shift;
-        new_state |= z;
-    }
-
-    new_state
+    PRESENT.s_box_layer(state)
 }
 
 pub(crate) fn inv_s_box_layer(state: u64) -> u64 {
-    let mut new_state = 0u64;
-    for i in 0..16 {
-        let shift = i * 4;
-        let mask = 0xf << shift;
-        let x = (state & mask) >> shift;
-        let y = S_INV[x as usize] as u64;
-        let z = y << shift;
-        new_state |= z;
-    }
-
-    new_state
+    PRESENT.inv_s_box_layer(state)
 }
 
 pub(crate) fn p_layer(state: u64) -> u64 {
-    let mut new_state = 0u64;
-
-    for (i, pi) in P.iter().enumerate() {
-        let mask = 1 << i;
-        let x = (state & mask) >> i;
-        let y = x << *pi;
-        new_state |= y;
-    }
-
-    new_state
+    PRESENT.p_layer(state)
 }
 
 pub(crate) fn inv_p_layer(state: u64) -> u64 {
-    let mut new_state = 0u64;
+    PRESENT.inv_p_layer(state)
+}
 
-    for (i, pi) in P_INV.iter().enumerate() {
-        let mask = 1 << i;
-        let x = (state & mask) >> i;
-        let y = x << *pi;
-        new_state |= y;
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
     }
 
-    new_state
+    let mut difference = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        difference |= x ^ y;
+    }
+    difference == 0
 }
 
 pub(crate) fn add_round_key(state: u64, round_key: u64) -> u64 {
     state ^ round_key
 }
 
-pub(crate) fn bytes_to_state(bytes: &[u8]) -> u64 {
+pub fn bytes_to_state(bytes: &[u8]) -> u64 {
     let mut state = 0u64;
     for (i, byte) in bytes.iter().take(BLOCK_SIZE_IN_BYTES).enumerate() {
-        let x = (*byte as u64) << (7 - i) * 8;
-        state |= x as u64;
+        let x = (*byte as u64) << ((7 - i) * 8);
+        state |= x;
     }
     state
 }
 
-pub(crate) fn state_to_bytes(state: u64) -> [u8; BLOCK_SIZE_IN_BYTES] {
+pub fn state_to_bytes(state: u64) -> [u8; BLOCK_SIZE_IN_BYTES] {
     let mut bytes = [0u8; BLOCK_SIZE_IN_BYTES];
-    for i in 0..BLOCK_SIZE_IN_BYTES {
-        let x = (state >> (7 - i) * 8) & 0xff;
-        bytes[i] = x as u8;
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let x = (state >> ((7 - i) * 8)) & 0xff;
+        *byte = x as u8;
     }
     bytes
 }
@@ -205,4 +260,15 @@ mod tests {
         ];
         assert_eq!(expected[..], padded[..]);
     }
+
+    #[test]
+    fn test_zeroize() {
+        let mut bytes = [0xffu8; 10];
+        zeroize(&mut bytes[2..]);
+        assert_eq!([0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0], bytes);
+
+        let mut words = vec![u64::MAX; 4];
+        zeroize(&mut words);
+        assert_eq!(vec![0u64; 4], words);
+    }
 }
@@ -0,0 +1,354 @@
+use std::error;
+use std::fmt;
+
+use super::BlockCipher;
+
+pub const IV_SIZE_IN_BYTES: usize = super::BLOCK_SIZE_IN_BYTES;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Padding {
+    None,
+    Zero,
+    Pkcs7,
+    Iso7816,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidLength,
+    InvalidIvLength,
+    InvalidPadding,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidLength => write!(
+                f,
+                "data length must be a multiple of {} bytes",
+                super::BLOCK_SIZE_IN_BYTES
+            ),
+            Error::InvalidIvLength => write!(f, "IV must be {} bytes", IV_SIZE_IN_BYTES),
+            Error::InvalidPadding => write!(f, "invalid padding"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+fn check_blocks(data: &[u8]) -> Result<(), Error> {
+    if !data.len().is_multiple_of(super::BLOCK_SIZE_IN_BYTES) {
+        return Err(Error::InvalidLength);
+    }
+    Ok(())
+}
+
+fn check_iv(iv: &[u8]) -> Result<u64, Error> {
+    if iv.len() != IV_SIZE_IN_BYTES {
+        return Err(Error::InvalidIvLength);
+    }
+    Ok(super::bytes_to_state(iv))
+}
+
+fn xor(data: &mut [u8], keystream: u64) {
+    let keystream = super::state_to_bytes(keystream);
+    for (x, y) in data.iter_mut().zip(keystream.iter()) {
+        *x ^= *y;
+    }
+}
+
+pub fn pad(padding: Padding, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let padding_length = super::BLOCK_SIZE_IN_BYTES - data.len() % super::BLOCK_SIZE_IN_BYTES;
+
+    match padding {
+        Padding::None => {
+            check_blocks(data)?;
+            Ok(data.to_vec())
+        }
+        Padding::Zero => Ok(super::pad(data)),
+        Padding::Pkcs7 => {
+            let mut padded = data.to_vec();
+            padded.resize(data.len() + padding_length, padding_length as u8);
+            Ok(padded)
+        }
+        Padding::Iso7816 => {
+            let mut padded = data.to_vec();
+            padded.push(0x80);
+            padded.resize(data.len() + padding_length, 0);
+            Ok(padded)
+        }
+    }
+}
+
+// Zero padding cannot be told apart from trailing zeroes in the data, which are removed too.
+pub fn unpad(padding: Padding, mut data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    check_blocks(&data)?;
+
+    match padding {
+        Padding::None => {}
+        Padding::Zero => {
+            while data.last() == Some(&0) {
+                data.pop();
+            }
+        }
+        Padding::Pkcs7 => {
+            let padding_length = match data.last() {
+                Some(&n) if n >= 1 && n as usize <= super::BLOCK_SIZE_IN_BYTES => n as usize,
+                _ => return Err(Error::InvalidPadding),
+            };
+            let start = data.len() - padding_length;
+            if data[start..].iter().any(|&x| x as usize != padding_length) {
+                return Err(Error::InvalidPadding);
+            }
+            data.truncate(start);
+        }
+        Padding::Iso7816 => {
+            let last_block = data.len().saturating_sub(super::BLOCK_SIZE_IN_BYTES);
+            match data[last_block..].iter().rposition(|&x| x != 0) {
+                Some(i) if data[last_block + i] == 0x80 => data.truncate(last_block + i),
+                _ => return Err(Error::InvalidPadding),
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+pub fn ecb_encrypt<C: BlockCipher>(cipher: &C, data: &mut [u8]) -> Result<(), Error> {
+    check_blocks(data)?;
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        let encrypted = cipher.encrypt_block(block);
+        block.copy_from_slice(&encrypted);
+    }
+    Ok(())
+}
+
+pub fn ecb_decrypt<C: BlockCipher>(cipher: &C, data: &mut [u8]) -> Result<(), Error> {
+    check_blocks(data)?;
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        let decrypted = cipher.decrypt_block(block);
+        block.copy_from_slice(&decrypted);
+    }
+    Ok(())
+}
+
+pub fn cbc_encrypt<C: BlockCipher>(cipher: &C, iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut previous = check_iv(iv)?;
+    check_blocks(data)?;
+
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        previous = cipher.encrypt_state(super::bytes_to_state(block) ^ previous);
+        block.copy_from_slice(&super::state_to_bytes(previous));
+    }
+    Ok(())
+}
+
+pub fn cbc_decrypt<C: BlockCipher>(cipher: &C, iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut previous = check_iv(iv)?;
+    check_blocks(data)?;
+
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        let state = super::bytes_to_state(block);
+        block.copy_from_slice(&super::state_to_bytes(
+            cipher.decrypt_state(state) ^ previous,
+        ));
+        previous = state;
+    }
+    Ok(())
+}
+
+// The nonce is the initial counter block, incremented as a big-endian integer. Encryption and
+// decryption are the same operation.
+pub fn ctr<C: BlockCipher>(cipher: &C, nonce: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut counter = check_iv(nonce)?;
+
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        xor(block, cipher.encrypt_state(counter));
+        counter = counter.wrapping_add(1);
+    }
+    Ok(())
+}
+
+// Full-block CFB, so a trailing partial block needs no padding.
+pub fn cfb_encrypt<C: BlockCipher>(cipher: &C, iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut previous = check_iv(iv)?;
+
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        xor(block, cipher.encrypt_state(previous));
+        previous = super::bytes_to_state(block);
+    }
+    Ok(())
+}
+
+pub fn cfb_decrypt<C: BlockCipher>(cipher: &C, iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut previous = check_iv(iv)?;
+
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        let state = super::bytes_to_state(block);
+        xor(block, cipher.encrypt_state(previous));
+        previous = state;
+    }
+    Ok(())
+}
+
+pub fn ofb<C: BlockCipher>(cipher: &C, iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut keystream = check_iv(iv)?;
+
+    for block in data.chunks_mut(super::BLOCK_SIZE_IN_BYTES) {
+        keystream = cipher.encrypt_state(keystream);
+        xor(block, keystream);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{present128, present80};
+    use super::*;
+
+    fn cipher() -> present80::Cipher {
+        present80::Cipher::new(present80::Key::new(&[0u8; 10]))
+    }
+
+    #[test]
+    fn test_pad() {
+        let data = [1u8, 2, 3];
+        assert_eq!(
+            vec![1u8, 2, 3, 5, 5, 5, 5, 5],
+            pad(Padding::Pkcs7, &data).unwrap()
+        );
+        assert_eq!(
+            vec![1u8, 2, 3, 0x80, 0, 0, 0, 0],
+            pad(Padding::Iso7816, &data).unwrap()
+        );
+        assert_eq!(
+            vec![1u8, 2, 3, 0, 0, 0, 0, 0],
+            pad(Padding::Zero, &data).unwrap()
+        );
+        assert_eq!(Err(Error::InvalidLength), pad(Padding::None, &data));
+
+        assert_eq!(vec![8u8; 8], pad(Padding::Pkcs7, &[]).unwrap());
+        assert_eq!(16, pad(Padding::Iso7816, &[0u8; 8]).unwrap().len());
+    }
+
+    #[test]
+    fn test_unpad() {
+        for padding in [Padding::Pkcs7, Padding::Iso7816].iter() {
+            for len in 0..17 {
+                let data: Vec<u8> = (0..len).collect();
+                let padded = pad(*padding, &data).unwrap();
+                assert_eq!(data, unpad(*padding, padded).unwrap());
+            }
+        }
+
+        assert_eq!(
+            vec![1u8, 2],
+            unpad(Padding::Zero, vec![1, 2, 0, 0, 0, 0, 0, 0]).unwrap()
+        );
+
+        assert_eq!(
+            Err(Error::InvalidPadding),
+            unpad(Padding::Pkcs7, vec![1, 2, 3, 4, 5, 6, 7, 0])
+        );
+        assert_eq!(
+            Err(Error::InvalidPadding),
+            unpad(Padding::Pkcs7, vec![1, 2, 3, 4, 5, 6, 2, 3])
+        );
+        assert_eq!(
+            Err(Error::InvalidPadding),
+            unpad(Padding::Iso7816, vec![1, 2, 3, 4, 5, 6, 7, 0])
+        );
+        assert_eq!(Err(Error::InvalidLength), unpad(Padding::None, vec![1]));
+    }
+
+    #[test]
+    fn test_ecb() {
+        let mut data = [0u8; 16];
+        ecb_encrypt(&cipher(), &mut data).unwrap();
+        assert_eq!(
+            present80::encrypt_block(&[0u8; 8], present80::Key::new(&[0u8; 10])),
+            data[8..]
+        );
+
+        ecb_decrypt(&cipher(), &mut data).unwrap();
+        assert_eq!([0u8; 16], data);
+
+        assert_eq!(
+            Err(Error::InvalidLength),
+            ecb_encrypt(&cipher(), &mut [0u8; 9])
+        );
+    }
+
+    #[test]
+    fn test_cbc() {
+        let cipher = cipher();
+        let iv = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let mut data = [0u8; 16];
+        cbc_encrypt(&cipher, &iv, &mut data).unwrap();
+
+        let c1 = cipher.encrypt_block(&iv);
+        let c2 = cipher.encrypt_block(&c1);
+        assert_eq!(c1, data[..8]);
+        assert_eq!(c2, data[8..]);
+
+        cbc_decrypt(&cipher, &iv, &mut data).unwrap();
+        assert_eq!([0u8; 16], data);
+
+        assert_eq!(
+            Err(Error::InvalidIvLength),
+            cbc_encrypt(&cipher, &iv[..7], &mut data)
+        );
+    }
+
+    #[test]
+    fn test_ctr() {
+        let cipher = cipher();
+        let mut data = [0u8; 12];
+        ctr(&cipher, &[0u8; 8], &mut data).unwrap();
+
+        assert_eq!(cipher.encrypt_block(&[0u8; 8]), data[..8]);
+        assert_eq!(
+            cipher.encrypt_block(&[0, 0, 0, 0, 0, 0, 0, 1])[..4],
+            data[8..]
+        );
+
+        ctr(&cipher, &[0u8; 8], &mut data).unwrap();
+        assert_eq!([0u8; 12], data);
+    }
+
+    #[test]
+    fn test_cfb() {
+        let cipher = cipher();
+        let iv = [8u8; 8];
+        let mut data = [0u8; 12];
+        cfb_encrypt(&cipher, &iv, &mut data).unwrap();
+
+        let c1 = cipher.encrypt_block(&iv);
+        let c2 = cipher.encrypt_block(&c1);
+        assert_eq!(c1, data[..8]);
+        assert_eq!(c2[..4], data[8..]);
+
+        cfb_decrypt(&cipher, &iv, &mut data).unwrap();
+        assert_eq!([0u8; 12], data);
+    }
+
+    #[test]
+    fn test_ofb() {
+        let cipher = present128::Cipher::new(present128::Key::new(&[0u8; 16]));
+        let iv = [8u8; 8];
+        let mut data = [0xffu8; 12];
+        ofb(&cipher, &iv, &mut data).unwrap();
+
+        let o1 = cipher.encrypt_block(&iv);
+        let o2 = cipher.encrypt_block(&o1);
+        for i in 0..8 {
+            assert_eq!(o1[i] ^ 0xff, data[i]);
+        }
+        for i in 0..4 {
+            assert_eq!(o2[i] ^ 0xff, data[8 + i]);
+        }
+
+        ofb(&cipher, &iv, &mut data).unwrap();
+        assert_eq!([0xffu8; 12], data);
+    }
+}
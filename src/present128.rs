@@ -1,10 +1,23 @@
 use std::fmt;
+use std::ops;
+use std::slice;
+
+use rayon::prelude::*;
 
 pub const KEY_LENGTH_IN_BYTES: usize = 16;
 
-#[derive(Clone, Copy)]
+// Key material is wiped on drop and only ever copied explicitly.
+#[derive(Clone)]
+struct RoundKeys([u64; super::NUM_ROUNDS + 1]);
+
+#[derive(Clone)]
 pub struct Key {
-    bytes: [u8; KEY_LENGTH_IN_BYTES],
+    pub(crate) bytes: [u8; KEY_LENGTH_IN_BYTES],
+}
+
+#[derive(Clone)]
+pub struct Cipher {
+    round_keys: RoundKeys,
 }
 
 struct KeyRegister {
@@ -17,25 +30,97 @@ impl Key {
         let mut b = [0u8; KEY_LENGTH_IN_BYTES];
         match bytes.len() {
             0 => {}
-            1...KEY_LENGTH_IN_BYTES => b[..bytes.len()].copy_from_slice(bytes),
+            1..=KEY_LENGTH_IN_BYTES => b[..bytes.len()].copy_from_slice(bytes),
             _ => b.copy_from_slice(&bytes[..KEY_LENGTH_IN_BYTES]),
         }
 
         Key { bytes: b }
     }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        super::zeroize(&mut self.bytes);
+    }
+}
+
+impl ops::Deref for RoundKeys {
+    type Target = [u64; super::NUM_ROUNDS + 1];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl ops::DerefMut for RoundKeys {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for RoundKeys {
+    fn drop(&mut self) {
+        super::zeroize(&mut self.0);
+    }
+}
+
+impl Drop for KeyRegister {
+    fn drop(&mut self) {
+        super::zeroize(slice::from_mut(&mut self.a));
+        super::zeroize(slice::from_mut(&mut self.b));
+    }
+}
+
+impl Cipher {
+    pub fn new(key: Key) -> Cipher {
+        Cipher {
+            round_keys: generate_round_keys(key),
+        }
+    }
+
+    pub fn encrypt_traced(&self, state: u64) -> super::trace::Trace {
+        super::trace::encrypt_traced(&super::PRESENT, state, &self.round_keys[..])
+    }
+}
+
+impl super::BlockCipher for Cipher {
+    fn encrypt_state(&self, state: u64) -> u64 {
+        encrypt(state, &self.round_keys)
+    }
+
+    fn decrypt_state(&self, state: u64) -> u64 {
+        decrypt(state, &self.round_keys)
+    }
 }
 
 impl KeyRegister {
     fn rotate(&mut self) {
-        unimplemented!()
+        let a = (self.a << 61) | (self.b >> 3);
+        let b = (self.b << 61) | (self.a >> 3);
+
+        self.a = a;
+        self.b = b;
     }
 
     fn update2(&mut self) {
-        unimplemented!()
+        let w = (self.a >> 60) & 0xf;
+        let x = (self.a >> 56) & 0xf;
+        let y = (super::S[w as usize] as u64) << 60;
+        let z = (super::S[x as usize] as u64) << 56;
+
+        self.a = y + z + (self.a & 0x00ffffffffffffff);
     }
 
     fn update3(&mut self, round_counter: u64) {
-        unimplemented!()
+        let p = (round_counter >> 2) & 0b111;
+        let q = (round_counter & 0b11) << 62;
+
+        self.a ^= p;
+        self.b ^= q;
     }
 
     fn update(&mut self, round_counter: u64) {
@@ -43,16 +128,103 @@ impl KeyRegister {
         self.update2();
         self.update3(round_counter);
     }
+
+    fn revert(&mut self, round_counter: u64) {
+        self.update3(round_counter);
+        self.revert2();
+        self.unrotate();
+    }
+
+    fn revert2(&mut self) {
+        let sbox_inv = super::PRESENT.sbox_inv();
+        let w = (self.a >> 60) & 0xf;
+        let x = (self.a >> 56) & 0xf;
+        let y = (sbox_inv[w as usize] as u64) << 60;
+        let z = (sbox_inv[x as usize] as u64) << 56;
+
+        self.a = y + z + (self.a & 0x00ffffffffffffff);
+    }
+
+    fn unrotate(&mut self) {
+        let a = (self.b << 3) | (self.a >> 61);
+        let b = (self.a << 3) | (self.b >> 61);
+
+        self.a = a;
+        self.b = b;
+    }
+}
+
+fn generate_round_keys(key: Key) -> RoundKeys {
+    let mut round_keys = RoundKeys([0u64; super::NUM_ROUNDS + 1]);
+    let mut key_register = KeyRegister::from(key);
+    for (i, round_key) in round_keys.iter_mut().take(super::NUM_ROUNDS).enumerate() {
+        *round_key = key_register.a;
+        key_register.update((i + 1) as u64);
+    }
+
+    round_keys[super::NUM_ROUNDS] = key_register.a;
+    round_keys
+}
+
+// The round keys for num_keys rounds from first_round on, counting rounds from 1 and taking the
+// key as the key register at the start of first_round.
+pub fn round_keys(key: Key, first_round: usize, num_keys: usize) -> super::ExpandedKey {
+    let mut round_keys = Vec::with_capacity(num_keys);
+    let mut key_register = KeyRegister::from(key);
+    for round_counter in first_round..first_round + num_keys {
+        round_keys.push(key_register.a);
+        if round_keys.len() < num_keys {
+            key_register.update(round_counter as u64);
+        }
+    }
+    super::ExpandedKey::from(round_keys)
+}
+
+impl super::spn::KeySchedule for Key {
+    fn round_keys(&self, num_rounds: usize) -> super::ExpandedKey {
+        round_keys(self.clone(), 1, num_rounds + 1)
+    }
+}
+
+// The key register at the start of round, as a key that round_keys can start from.
+pub fn key_at_round(key: Key, round: usize) -> Key {
+    let mut key_register = KeyRegister::from(key);
+    for round_counter in 1..round {
+        key_register.update(round_counter as u64);
+    }
+    key_register.to_key()
+}
+
+// The master key, from the key register at the start of round as key_at_round gives it.
+pub fn master_key(key: Key, round: usize) -> Key {
+    let mut key_register = KeyRegister::from(key);
+    for round_counter in (1..round).rev() {
+        key_register.revert(round_counter as u64);
+    }
+    key_register.to_key()
+}
+
+impl KeyRegister {
+    fn to_key(&self) -> Key {
+        let mut bytes = [0u8; KEY_LENGTH_IN_BYTES];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = if i < 8 {
+                (self.a >> (56 - i * 8)) as u8
+            } else {
+                (self.b >> (120 - i * 8)) as u8
+            };
+        }
+
+        let key = Key::new(&bytes);
+        super::zeroize(&mut bytes);
+        key
+    }
 }
 
 impl From<Key> for KeyRegister {
     fn from(key: Key) -> Self {
         let (mut a, mut b) = (0u64, 0u64);
         for (i, x) in key.bytes.iter().enumerate() {
-            if i > KEY_LENGTH_IN_BYTES - 1 {
-                break;
-            }
-
             let byte = *x as u64;
             if i < 8 {
                 let shift = 56 - i * 8;
@@ -73,58 +245,130 @@ impl fmt::Debug for KeyRegister {
     }
 }
 
-fn encrypt(state: u64, key: Key) -> u64 {
-    let mut state = state;
-    let mut key_register = KeyRegister::from(key);
+fn decrypt(state: u64, round_keys: &RoundKeys) -> u64 {
+    super::PRESENT.decrypt(state, &round_keys[..])
+}
+
+fn encrypt(state: u64, round_keys: &RoundKeys) -> u64 {
+    super::PRESENT.encrypt(state, &round_keys[..])
+}
+
+pub fn ecb_encrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
 
-    for i in 0..super::NUM_ROUNDS {
-        let round_key = key_register.a;
-        state = super::add_round_key(state, round_key);
-        state = super::s_box_layer(state);
-        state = super::p_layer(state);
+    let blocks: Vec<[u8; 8]> = data.chunks(super::BLOCK_SIZE_IN_BYTES)
+        .map(super::bytes_to_state)
+        .map(|state| encrypt(state, &round_keys))
+        .map(super::state_to_bytes)
+        .collect();
 
-        key_register.update((i + 1) as u64);
+    let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
+    let mut encrypted: Vec<u8> = Vec::with_capacity(num_blocks);
+    for block in blocks.iter() {
+        encrypted.extend(block.iter());
     }
 
-    let round_key = key_register.a;
-    state = super::add_round_key(state, round_key);
+    encrypted
+}
+
+pub fn ecb_decrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
 
-    state
+    let blocks: Vec<[u8; 8]> = data.chunks(super::BLOCK_SIZE_IN_BYTES)
+        .map(super::bytes_to_state)
+        .map(|state| decrypt(state, &round_keys))
+        .map(super::state_to_bytes)
+        .collect();
+
+    let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
+    let mut decrypted: Vec<u8> = Vec::with_capacity(num_blocks);
+    for block in blocks.iter() {
+        decrypted.extend(block.iter());
+    }
+
+    decrypted
 }
 
-pub fn ecb_encrypt(data: &[u8], key: Key) -> Vec<u8> {
-    let padded = super::pad(data);
-    let num_blocks = padded.len();
+pub fn par_ecb_encrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
 
-    let mut encrypted: Vec<u8> = Vec::with_capacity(num_blocks);
+    let blocks: Vec<[u8; 8]> = data.par_chunks(super::BLOCK_SIZE_IN_BYTES)
+        .map(super::bytes_to_state)
+        .map(|state| encrypt(state, &round_keys))
+        .map(super::state_to_bytes)
+        .collect();
 
-    for i in 0..num_blocks / 8 {
-        let encrypted_block = _encrypt_block(&padded[8 * i..8 * (i + 1)], key);
-        encrypted.extend(encrypted_block.iter());
+    let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
+    let mut encrypted: Vec<u8> = Vec::with_capacity(num_blocks);
+    for block in blocks.iter() {
+        encrypted.extend(block.iter());
     }
 
     encrypted
 }
 
-fn _encrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
+pub fn par_ecb_decrypt(data: &[u8], key: Key) -> Vec<u8> {
+    let round_keys = generate_round_keys(key);
+
+    let blocks: Vec<[u8; 8]> = data.par_chunks(super::BLOCK_SIZE_IN_BYTES)
+        .map(super::bytes_to_state)
+        .map(|state| decrypt(state, &round_keys))
+        .map(super::state_to_bytes)
+        .collect();
+
+    let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
+    let mut decrypted: Vec<u8> = Vec::with_capacity(num_blocks);
+    for block in blocks.iter() {
+        decrypted.extend(block.iter());
+    }
+
+    decrypted
+}
+
+pub fn encrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
     let state = super::bytes_to_state(data);
-    let encrypted = encrypt(state, key);
+    let round_keys = generate_round_keys(key);
+    let encrypted = encrypt(state, &round_keys);
 
     super::state_to_bytes(encrypted)
 }
 
-pub fn encrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
-    if data.len() < super::BLOCK_SIZE_IN_BYTES {
-        let mut padded = [0u8; super::BLOCK_SIZE_IN_BYTES];
-        &padded[..data.len()].copy_from_slice(data);
-        _encrypt_block(&padded[..], key)
-    } else {
-        _encrypt_block(&data[..super::BLOCK_SIZE_IN_BYTES], key)
+pub fn encrypt_traced(data: &[u8], key: Key) -> super::trace::Trace {
+    Cipher::new(key).encrypt_traced(super::bytes_to_state(data))
+}
+
+pub fn decrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
+    let state = super::bytes_to_state(data);
+    let round_keys = generate_round_keys(key);
+
+    let decrypted = decrypt(state, &round_keys);
+
+    super::state_to_bytes(decrypted)
+}
+
+// Decrypts with the key register at the start of the last round, as
+// key_at_round(key, NUM_ROUNDS + 1) gives it, and runs the key schedule backwards instead of
+// storing the round keys.
+pub fn decrypt_block_on_the_fly(
+    data: &[u8],
+    last_round_key: Key,
+) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
+    let mut key_register = KeyRegister::from(last_round_key);
+    let mut state = super::add_round_key(super::bytes_to_state(data), key_register.a);
+    for round_counter in (1..=super::NUM_ROUNDS).rev() {
+        key_register.revert(round_counter as u64);
+        state = super::inv_p_layer(state);
+        state = super::inv_s_box_layer(state);
+        state = super::add_round_key(state, key_register.a);
     }
+
+    super::state_to_bytes(state)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::mem;
+
     use super::*;
 
     #[test]
@@ -166,4 +410,66 @@ mod tests {
         assert_eq!(key_register.a, 1u64);
         assert_eq!(key_register.b, 1u64);
     }
+
+    #[test]
+    fn test_key_register_rotate() {
+        let mut key_register = KeyRegister { a: 0b1, b: 0b1000 };
+        key_register.rotate();
+
+        let a: u64 = (1 << 61) + 1;
+        let b: u64 = 0;
+
+        assert_eq!(a, key_register.a);
+        assert_eq!(b, key_register.b);
+    }
+
+    #[test]
+    fn test_key_register_update1() {
+        let mut key_register = KeyRegister { a: 0, b: 0 };
+        key_register.update(1);
+
+        let a: u64 = 0xcc << 56;
+        let b: u64 = 1 << 62;
+
+        assert_eq!(a, key_register.a);
+        assert_eq!(b, key_register.b);
+    }
+
+    #[test]
+    fn test_key_register_revert() {
+        let mut key_register = KeyRegister {
+            a: 0x0123456789abcdef,
+            b: 0xfedcba9876543210,
+        };
+        key_register.update(27);
+        key_register.revert(27);
+        assert_eq!(0x0123456789abcdef, key_register.a);
+        assert_eq!(0xfedcba9876543210, key_register.b);
+    }
+
+    #[test]
+    fn test_master_key() {
+        let bytes = [
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54,
+            0x32, 0x10,
+        ];
+        for &round in [1, 2, 17, 32].iter() {
+            let key = key_at_round(Key::new(&bytes), round);
+            assert_eq!(bytes, master_key(key, round).bytes);
+        }
+    }
+
+    #[test]
+    fn test_zeroize_on_drop() {
+        let mut key = mem::ManuallyDrop::new(Key::new(&[0xff; 16]));
+        let mut round_keys = mem::ManuallyDrop::new(generate_round_keys(Key::new(&[0xff; 16])));
+        assert!(round_keys.iter().all(|&round_key| round_key != 0));
+
+        unsafe {
+            mem::ManuallyDrop::drop(&mut key);
+            mem::ManuallyDrop::drop(&mut round_keys);
+        }
+        assert_eq!([0u8; KEY_LENGTH_IN_BYTES], key.bytes);
+        assert_eq!([0u64; super::super::NUM_ROUNDS + 1], round_keys.0);
+    }
 }
@@ -1,17 +1,24 @@
-use std::io::Read;
+use std::ops;
+use std::slice;
 
 use rayon::prelude::*;
 
 pub const KEY_LENGTH_IN_BYTES: usize = 10;
 
-type RoundKeys = [u64; super::NUM_ROUNDS + 1];
+// Key material is wiped on drop and only ever copied explicitly.
+#[derive(Clone)]
+struct RoundKeys([u64; super::NUM_ROUNDS + 1]);
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Key {
     bytes: [u8; KEY_LENGTH_IN_BYTES],
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
+pub struct Cipher {
+    round_keys: RoundKeys,
+}
+
 struct KeyRegister {
     a: u64,
     b: u64,
@@ -20,10 +27,70 @@ struct KeyRegister {
 impl Key {
     pub fn new(bytes: &[u8]) -> Key {
         let mut b = [0u8; KEY_LENGTH_IN_BYTES];
-        bytes.take(KEY_LENGTH_IN_BYTES as u64).read(&mut b).unwrap();
+        let len = bytes.len().min(KEY_LENGTH_IN_BYTES);
+        b[..len].copy_from_slice(&bytes[..len]);
 
         Key { bytes: b }
     }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        super::zeroize(&mut self.bytes);
+    }
+}
+
+impl ops::Deref for RoundKeys {
+    type Target = [u64; super::NUM_ROUNDS + 1];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl ops::DerefMut for RoundKeys {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for RoundKeys {
+    fn drop(&mut self) {
+        super::zeroize(&mut self.0);
+    }
+}
+
+impl Drop for KeyRegister {
+    fn drop(&mut self) {
+        super::zeroize(slice::from_mut(&mut self.a));
+        super::zeroize(slice::from_mut(&mut self.b));
+    }
+}
+
+impl Cipher {
+    pub fn new(key: Key) -> Cipher {
+        Cipher {
+            round_keys: generate_round_keys(key),
+        }
+    }
+
+    pub fn encrypt_traced(&self, state: u64) -> super::trace::Trace {
+        super::trace::encrypt_traced(&super::PRESENT, state, &self.round_keys[..])
+    }
+}
+
+impl super::BlockCipher for Cipher {
+    fn encrypt_state(&self, state: u64) -> u64 {
+        encrypt(state, &self.round_keys)
+    }
+
+    fn decrypt_state(&self, state: u64) -> u64 {
+        decrypt(state, &self.round_keys)
+    }
 }
 
 impl KeyRegister {
@@ -76,13 +143,37 @@ impl KeyRegister {
         self.update2();
         self.update3(round_counter as u64);
     }
+
+    // Undoes update, step by step in reverse. XORing in the round counter is its own inverse.
+    fn revert(&mut self, round_counter: usize) {
+        self.update3(round_counter as u64);
+        self.revert2();
+        self.unrotate();
+    }
+
+    fn revert2(&mut self) {
+        let w = (self.a >> 60) & 0xf;
+        let x = super::PRESENT.sbox_inv()[w as usize];
+        self.a = (x as u64) << 60 | (self.a & 0x0fffffffffffffff);
+    }
+
+    // Rotating the 80-bit register left by 61 is undone by rotating it left by 19.
+    fn unrotate(&mut self) {
+        let w = self.a << 19;
+        let x = self.b >> 45;
+        let y = self.a >> 61;
+        let z = (self.a >> 45) & 0xffff;
+
+        self.a = w | x | y;
+        self.b = z << 48;
+    }
 }
 
-fn generate_round_keys(key: Key) -> [u64; super::NUM_ROUNDS + 1] {
-    let mut round_keys = [0u64; super::NUM_ROUNDS + 1];
+fn generate_round_keys(key: Key) -> RoundKeys {
+    let mut round_keys = RoundKeys([0u64; super::NUM_ROUNDS + 1]);
     let mut key_register = KeyRegister::from(key);
-    for i in 0..super::NUM_ROUNDS {
-        round_keys[i] = key_register.a;
+    for (i, round_key) in round_keys.iter_mut().take(super::NUM_ROUNDS).enumerate() {
+        *round_key = key_register.a;
         key_register.update(i + 1);
     }
 
@@ -90,6 +181,62 @@ fn generate_round_keys(key: Key) -> [u64; super::NUM_ROUNDS + 1] {
     round_keys
 }
 
+// The round keys for num_keys rounds from first_round on, counting rounds from 1 and taking the
+// key as the key register at the start of first_round.
+pub fn round_keys(key: Key, first_round: usize, num_keys: usize) -> super::ExpandedKey {
+    let mut round_keys = Vec::with_capacity(num_keys);
+    let mut key_register = KeyRegister::from(key);
+    for round_counter in first_round..first_round + num_keys {
+        round_keys.push(key_register.a);
+        if round_keys.len() < num_keys {
+            key_register.update(round_counter);
+        }
+    }
+    super::ExpandedKey::from(round_keys)
+}
+
+// The round counter only has 5 bits, so it wraps around for variants with more than 31 rounds.
+impl super::spn::KeySchedule for Key {
+    fn round_keys(&self, num_rounds: usize) -> super::ExpandedKey {
+        round_keys(self.clone(), 1, num_rounds + 1)
+    }
+}
+
+// The key register at the start of round, as a key that round_keys can start from.
+pub fn key_at_round(key: Key, round: usize) -> Key {
+    let mut key_register = KeyRegister::from(key);
+    for round_counter in 1..round {
+        key_register.update(round_counter);
+    }
+    key_register.to_key()
+}
+
+// The master key, from the key register at the start of round as key_at_round gives it.
+pub fn master_key(key: Key, round: usize) -> Key {
+    let mut key_register = KeyRegister::from(key);
+    for round_counter in (1..round).rev() {
+        key_register.revert(round_counter);
+    }
+    key_register.to_key()
+}
+
+impl KeyRegister {
+    fn to_key(&self) -> Key {
+        let mut bytes = [0u8; KEY_LENGTH_IN_BYTES];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = if i < 8 {
+                (self.a >> (56 - i * 8)) as u8
+            } else {
+                (self.b >> (120 - i * 8)) as u8
+            };
+        }
+
+        let key = Key::new(&bytes);
+        super::zeroize(&mut bytes);
+        key
+    }
+}
+
 impl From<Key> for KeyRegister {
     fn from(key: Key) -> Self {
         let (mut a, mut b) = (0u64, 0u64);
@@ -109,38 +256,20 @@ impl From<Key> for KeyRegister {
 }
 
 fn decrypt(state: u64, round_keys: &RoundKeys) -> u64 {
-    let mut state = state;
-    state = super::add_round_key(state, round_keys[super::NUM_ROUNDS]);
-
-    for i in (0..super::NUM_ROUNDS).rev() {
-        state = super::inv_p_layer(state);
-        state = super::inv_s_box_layer(state);
-        state = super::add_round_key(state, round_keys[i]);
-    }
-
-    state
+    super::PRESENT.decrypt(state, &round_keys[..])
 }
 
 fn encrypt(state: u64, round_keys: &RoundKeys) -> u64 {
-    let mut state = state;
-
-    for i in 0..super::NUM_ROUNDS {
-        state = super::add_round_key(state, round_keys[i]);
-        state = super::s_box_layer(state);
-        state = super::p_layer(state);
-    }
-
-    state = super::add_round_key(state, round_keys[super::NUM_ROUNDS]);
-    state
+    super::PRESENT.encrypt(state, &round_keys[..])
 }
 
 pub fn ecb_encrypt(data: &[u8], key: Key) -> Vec<u8> {
     let round_keys = generate_round_keys(key);
 
     let blocks: Vec<[u8; 8]> = data.chunks(super::BLOCK_SIZE_IN_BYTES)
-        .map(|bytes| super::bytes_to_state(bytes))
+        .map(super::bytes_to_state)
         .map(|state| encrypt(state, &round_keys))
-        .map(|state| super::state_to_bytes(state))
+        .map(super::state_to_bytes)
         .collect();
 
     let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
@@ -156,9 +285,9 @@ pub fn ecb_decrypt(data: &[u8], key: Key) -> Vec<u8> {
     let round_keys = generate_round_keys(key);
 
     let blocks: Vec<[u8; 8]> = data.chunks(super::BLOCK_SIZE_IN_BYTES)
-        .map(|bytes| super::bytes_to_state(bytes))
+        .map(super::bytes_to_state)
         .map(|state| decrypt(state, &round_keys))
-        .map(|state| super::state_to_bytes(state))
+        .map(super::state_to_bytes)
         .collect();
 
     let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
@@ -174,9 +303,9 @@ pub fn par_ecb_encrypt(data: &[u8], key: Key) -> Vec<u8> {
     let round_keys = generate_round_keys(key);
 
     let blocks: Vec<[u8; 8]> = data.par_chunks(super::BLOCK_SIZE_IN_BYTES)
-        .map(|bytes| super::bytes_to_state(bytes))
+        .map(super::bytes_to_state)
         .map(|state| encrypt(state, &round_keys))
-        .map(|state| super::state_to_bytes(state))
+        .map(super::state_to_bytes)
         .collect();
 
     let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
@@ -192,9 +321,9 @@ pub fn par_ecb_decrypt(data: &[u8], key: Key) -> Vec<u8> {
     let round_keys = generate_round_keys(key);
 
     let blocks: Vec<[u8; 8]> = data.par_chunks(super::BLOCK_SIZE_IN_BYTES)
-        .map(|bytes| super::bytes_to_state(bytes))
+        .map(super::bytes_to_state)
         .map(|state| decrypt(state, &round_keys))
-        .map(|state| super::state_to_bytes(state))
+        .map(super::state_to_bytes)
         .collect();
 
     let num_blocks = blocks.len() * super::BLOCK_SIZE_IN_BYTES;
@@ -214,6 +343,10 @@ pub fn encrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES]
     super::state_to_bytes(encrypted)
 }
 
+pub fn encrypt_traced(data: &[u8], key: Key) -> super::trace::Trace {
+    Cipher::new(key).encrypt_traced(super::bytes_to_state(data))
+}
+
 pub fn decrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
     let state = super::bytes_to_state(data);
     let round_keys = generate_round_keys(key);
@@ -223,8 +356,28 @@ pub fn decrypt_block(data: &[u8], key: Key) -> [u8; super::BLOCK_SIZE_IN_BYTES]
     super::state_to_bytes(decrypted)
 }
 
+// Decrypts with the key register at the start of the last round, as
+// key_at_round(key, NUM_ROUNDS + 1) gives it, without ever holding more than one round key.
+pub fn decrypt_block_on_the_fly(
+    data: &[u8],
+    last_round_key: Key,
+) -> [u8; super::BLOCK_SIZE_IN_BYTES] {
+    let mut key_register = KeyRegister::from(last_round_key);
+    let mut state = super::add_round_key(super::bytes_to_state(data), key_register.a);
+    for round_counter in (1..=super::NUM_ROUNDS).rev() {
+        key_register.revert(round_counter);
+        state = super::inv_p_layer(state);
+        state = super::inv_s_box_layer(state);
+        state = super::add_round_key(state, key_register.a);
+    }
+
+    super::state_to_bytes(state)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::mem;
+
     use super::*;
 
     #[test]
@@ -282,4 +435,39 @@ mod tests {
         assert_eq!(a, key_register.a);
         assert_eq!(b, key_register.b);
     }
+
+    #[test]
+    fn test_key_register_revert() {
+        let mut key_register = KeyRegister {
+            a: 0x0123456789abcdef,
+            b: 0xfedc << 48,
+        };
+        key_register.update(5);
+        key_register.revert(5);
+        assert_eq!(0x0123456789abcdef, key_register.a);
+        assert_eq!(0xfedc << 48, key_register.b);
+    }
+
+    #[test]
+    fn test_master_key() {
+        let bytes = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc];
+        for &round in [1, 2, 17, 32].iter() {
+            let key = key_at_round(Key::new(&bytes), round);
+            assert_eq!(bytes, master_key(key, round).bytes);
+        }
+    }
+
+    #[test]
+    fn test_zeroize_on_drop() {
+        let mut key = mem::ManuallyDrop::new(Key::new(&[0xff; 10]));
+        let mut round_keys = mem::ManuallyDrop::new(generate_round_keys(Key::new(&[0xff; 10])));
+        assert!(round_keys.iter().all(|&round_key| round_key != 0));
+
+        unsafe {
+            mem::ManuallyDrop::drop(&mut key);
+            mem::ManuallyDrop::drop(&mut round_keys);
+        }
+        assert_eq!([0u8; KEY_LENGTH_IN_BYTES], key.bytes);
+        assert_eq!([0u64; super::super::NUM_ROUNDS + 1], round_keys.0);
+    }
 }
@@ -0,0 +1,213 @@
+use std::error;
+use std::fmt;
+
+use super::{present128, present80, BlockCipher, ExpandedKey, NUM_ROUNDS};
+
+// Rounds are counted from 1 as in the paper, so the full cipher is rounds 1 to 31 followed by
+// the final key whitening with K32.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rounds {
+    pub first: usize,
+    pub count: usize,
+    pub whitening: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidRounds(Rounds),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidRounds(rounds) => write!(
+                f,
+                "cannot run {} rounds from round {}, PRESENT only has rounds 1 to {}",
+                rounds.count, rounds.first, NUM_ROUNDS
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl Rounds {
+    pub fn new(count: usize) -> Rounds {
+        Rounds {
+            first: 1,
+            count,
+            whitening: true,
+        }
+    }
+
+    pub fn full() -> Rounds {
+        Rounds::new(NUM_ROUNDS)
+    }
+
+    // The round counter only has 5 bits, so the key schedule cannot go past round 31.
+    fn validate(&self) -> Result<(), Error> {
+        if self.first == 0 || self.first + self.count > NUM_ROUNDS + 1 {
+            return Err(Error::InvalidRounds(*self));
+        }
+        Ok(())
+    }
+
+    fn num_keys(&self) -> usize {
+        self.count + self.whitening as usize
+    }
+}
+
+// The key is taken as the key register at the start of the first round, which
+// present80::key_at_round and present128::key_at_round compute from a cipher key.
+pub struct Cipher {
+    round_keys: ExpandedKey,
+    rounds: Rounds,
+}
+
+impl Cipher {
+    pub fn new80(key: present80::Key, rounds: Rounds) -> Result<Cipher, Error> {
+        rounds.validate()?;
+        Ok(Cipher {
+            round_keys: present80::round_keys(key, rounds.first, rounds.num_keys()),
+            rounds,
+        })
+    }
+
+    pub fn new128(key: present128::Key, rounds: Rounds) -> Result<Cipher, Error> {
+        rounds.validate()?;
+        Ok(Cipher {
+            round_keys: present128::round_keys(key, rounds.first, rounds.num_keys()),
+            rounds,
+        })
+    }
+
+    pub fn rounds(&self) -> Rounds {
+        self.rounds
+    }
+
+    pub fn round_keys(&self) -> &[u64] {
+        &self.round_keys
+    }
+}
+
+impl BlockCipher for Cipher {
+    fn encrypt_state(&self, state: u64) -> u64 {
+        let mut state = state;
+        for round_key in self.round_keys.iter().take(self.rounds.count) {
+            state = super::add_round_key(state, *round_key);
+            state = super::s_box_layer(state);
+            state = super::p_layer(state);
+        }
+
+        if self.rounds.whitening {
+            state = super::add_round_key(state, self.round_keys[self.rounds.count]);
+        }
+        state
+    }
+
+    fn decrypt_state(&self, state: u64) -> u64 {
+        let mut state = state;
+        if self.rounds.whitening {
+            state = super::add_round_key(state, self.round_keys[self.rounds.count]);
+        }
+
+        for round_key in self.round_keys.iter().take(self.rounds.count).rev() {
+            state = super::inv_p_layer(state);
+            state = super::inv_s_box_layer(state);
+            state = super::add_round_key(state, *round_key);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: u64 = 0x0123456789abcdef;
+
+    #[test]
+    fn test_full_rounds() {
+        for key in [[0u8; 16], [0xff; 16]].iter() {
+            let cipher = Cipher::new80(present80::Key::new(key), Rounds::full()).unwrap();
+            let expected = present80::Cipher::new(present80::Key::new(key));
+            assert_eq!(
+                expected.encrypt_state(PLAINTEXT),
+                cipher.encrypt_state(PLAINTEXT)
+            );
+
+            let cipher = Cipher::new128(present128::Key::new(key), Rounds::full()).unwrap();
+            let expected = present128::Cipher::new(present128::Key::new(key));
+            assert_eq!(
+                expected.encrypt_state(PLAINTEXT),
+                cipher.encrypt_state(PLAINTEXT)
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &(first, count) in [(1, 0), (1, 1), (5, 4), (20, 12)].iter() {
+            for &whitening in [false, true].iter() {
+                let rounds = Rounds {
+                    first,
+                    count,
+                    whitening,
+                };
+                let cipher = Cipher::new80(present80::Key::new(&[0x42; 10]), rounds).unwrap();
+                assert_eq!(count + whitening as usize, cipher.round_keys().len());
+
+                let encrypted = cipher.encrypt_state(PLAINTEXT);
+                assert_eq!(PLAINTEXT, cipher.decrypt_state(encrypted));
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_rounds() {
+        let key = [0x5a; 16];
+        let head = Rounds {
+            first: 1,
+            count: 10,
+            whitening: false,
+        };
+        let tail = Rounds {
+            first: 11,
+            count: 21,
+            whitening: true,
+        };
+
+        let expected = present80::Cipher::new(present80::Key::new(&key));
+        let head80 = Cipher::new80(present80::Key::new(&key), head).unwrap();
+        let key80 = present80::key_at_round(present80::Key::new(&key), 11);
+        let tail80 = Cipher::new80(key80, tail).unwrap();
+        assert_eq!(
+            expected.encrypt_state(PLAINTEXT),
+            tail80.encrypt_state(head80.encrypt_state(PLAINTEXT))
+        );
+
+        let expected = present128::Cipher::new(present128::Key::new(&key));
+        let head128 = Cipher::new128(present128::Key::new(&key), head).unwrap();
+        let key128 = present128::key_at_round(present128::Key::new(&key), 11);
+        let tail128 = Cipher::new128(key128, tail).unwrap();
+        assert_eq!(
+            expected.encrypt_state(PLAINTEXT),
+            tail128.encrypt_state(head128.encrypt_state(PLAINTEXT))
+        );
+    }
+
+    #[test]
+    fn test_invalid_rounds() {
+        for &(first, count) in [(0, 1), (1, 32), (31, 2)].iter() {
+            let rounds = Rounds {
+                first,
+                count,
+                whitening: true,
+            };
+            match Cipher::new80(present80::Key::new(&[0; 10]), rounds) {
+                Err(Error::InvalidRounds(r)) => assert_eq!(rounds, r),
+                Ok(_) => panic!("{:?} should be invalid", rounds),
+            }
+        }
+    }
+}
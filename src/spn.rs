@@ -0,0 +1,287 @@
+use std::error;
+use std::fmt;
+
+use super::analysis::{self, SBOX_SIZE};
+use super::{BlockCipher, ExpandedKey};
+
+pub const STATE_SIZE_IN_BITS: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidSBox(analysis::Error),
+    InvalidPermutationEntry(u8),
+    PermutationNotBijective,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidSBox(ref e) => e.fmt(f),
+            Error::InvalidPermutationEntry(x) => write!(
+                f,
+                "permutation entry {} is not a bit position below {}",
+                x, STATE_SIZE_IN_BITS
+            ),
+            Error::PermutationNotBijective => write!(f, "bit permutation is not a permutation"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// A 64-bit substitution-permutation network with one 4-bit S-box applied to every nibble and a
+// bit permutation that sends bit i to bit permutation[i]. Nibble and bit 0 are the least
+// significant. The inverse tables are always derived from the forward ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spn {
+    sbox: [u8; SBOX_SIZE],
+    sbox_inv: [u8; SBOX_SIZE],
+    permutation: [u8; STATE_SIZE_IN_BITS],
+    permutation_inv: [u8; STATE_SIZE_IN_BITS],
+}
+
+const fn invert_permutation(
+    permutation: &[u8; STATE_SIZE_IN_BITS],
+) -> Result<[u8; STATE_SIZE_IN_BITS], Error> {
+    let mut inverse = [0u8; STATE_SIZE_IN_BITS];
+    let mut seen = [false; STATE_SIZE_IN_BITS];
+    let mut i = 0;
+    while i < STATE_SIZE_IN_BITS {
+        let j = permutation[i] as usize;
+        if j >= STATE_SIZE_IN_BITS {
+            return Err(Error::InvalidPermutationEntry(permutation[i]));
+        }
+        if seen[j] {
+            return Err(Error::PermutationNotBijective);
+        }
+        seen[j] = true;
+        inverse[j] = i as u8;
+        i += 1;
+    }
+    Ok(inverse)
+}
+
+fn substitute(state: u64, sbox: &[u8; SBOX_SIZE]) -> u64 {
+    let mut new_state = 0u64;
+    for i in 0..16 {
+        let shift = i * 4;
+        let x = (state >> shift) & 0xf;
+        new_state |= (sbox[x as usize] as u64) << shift;
+    }
+    new_state
+}
+
+fn permute(state: u64, permutation: &[u8; STATE_SIZE_IN_BITS]) -> u64 {
+    let mut new_state = 0u64;
+    for (i, pi) in permutation.iter().enumerate() {
+        new_state |= ((state >> i) & 1) << *pi;
+    }
+    new_state
+}
+
+impl Spn {
+    // A const fn, so that a constant SPN with tables that do not invert fails to compile.
+    pub const fn new(
+        sbox: [u8; SBOX_SIZE],
+        permutation: [u8; STATE_SIZE_IN_BITS],
+    ) -> Result<Spn, Error> {
+        let sbox_inv = match analysis::inverse(&sbox) {
+            Ok(sbox_inv) => sbox_inv,
+            Err(e) => return Err(Error::InvalidSBox(e)),
+        };
+        let permutation_inv = match invert_permutation(&permutation) {
+            Ok(permutation_inv) => permutation_inv,
+            Err(e) => return Err(e),
+        };
+
+        Ok(Spn {
+            sbox,
+            sbox_inv,
+            permutation,
+            permutation_inv,
+        })
+    }
+
+    pub fn sbox(&self) -> &[u8; SBOX_SIZE] {
+        &self.sbox
+    }
+
+    pub fn sbox_inv(&self) -> &[u8; SBOX_SIZE] {
+        &self.sbox_inv
+    }
+
+    pub fn permutation(&self) -> &[u8; STATE_SIZE_IN_BITS] {
+        &self.permutation
+    }
+
+    pub fn permutation_inv(&self) -> &[u8; STATE_SIZE_IN_BITS] {
+        &self.permutation_inv
+    }
+
+    pub fn s_box_layer(&self, state: u64) -> u64 {
+        substitute(state, &self.sbox)
+    }
+
+    pub fn inv_s_box_layer(&self, state: u64) -> u64 {
+        substitute(state, &self.sbox_inv)
+    }
+
+    pub fn p_layer(&self, state: u64) -> u64 {
+        permute(state, &self.permutation)
+    }
+
+    pub fn inv_p_layer(&self, state: u64) -> u64 {
+        permute(state, &self.permutation_inv)
+    }
+
+    // Each round adds its key and applies the S-box and permutation layers, and the last key is
+    // added after the final round, so there is one more key than rounds.
+    pub fn encrypt(&self, state: u64, round_keys: &[u64]) -> u64 {
+        let (last, round_keys) = round_keys.split_last().expect("no round keys");
+        let mut state = state;
+        for round_key in round_keys.iter() {
+            state = super::add_round_key(state, *round_key);
+            state = self.s_box_layer(state);
+            state = self.p_layer(state);
+        }
+        super::add_round_key(state, *last)
+    }
+
+    pub fn decrypt(&self, state: u64, round_keys: &[u64]) -> u64 {
+        let (last, round_keys) = round_keys.split_last().expect("no round keys");
+        let mut state = super::add_round_key(state, *last);
+        for round_key in round_keys.iter().rev() {
+            state = self.inv_p_layer(state);
+            state = self.inv_s_box_layer(state);
+            state = super::add_round_key(state, *round_key);
+        }
+        state
+    }
+}
+
+pub trait KeySchedule {
+    // The num_rounds + 1 keys for Spn::encrypt.
+    fn round_keys(&self, num_rounds: usize) -> ExpandedKey;
+}
+
+pub struct Cipher {
+    spn: Spn,
+    round_keys: ExpandedKey,
+}
+
+impl Cipher {
+    pub fn new<K: KeySchedule>(spn: Spn, key: &K, num_rounds: usize) -> Cipher {
+        let round_keys = key.round_keys(num_rounds);
+        assert_eq!(
+            num_rounds + 1,
+            round_keys.len(),
+            "wrong number of round keys"
+        );
+        Cipher { spn, round_keys }
+    }
+
+    pub fn spn(&self) -> &Spn {
+        &self.spn
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.round_keys.len() - 1
+    }
+
+    pub fn round_keys(&self) -> &[u64] {
+        &self.round_keys
+    }
+
+    pub fn encrypt_traced(&self, state: u64) -> super::trace::Trace {
+        super::trace::encrypt_traced(&self.spn, state, &self.round_keys)
+    }
+}
+
+impl BlockCipher for Cipher {
+    fn encrypt_state(&self, state: u64) -> u64 {
+        self.spn.encrypt(state, &self.round_keys)
+    }
+
+    fn decrypt_state(&self, state: u64) -> u64 {
+        self.spn.decrypt(state, &self.round_keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{present128, present80, NUM_ROUNDS, P, PRESENT, S};
+    use super::*;
+
+    const PLAINTEXT: u64 = 0x0123456789abcdef;
+
+    #[test]
+    fn test_present_tables() {
+        let sbox_inv = [5, 0xE, 0xF, 8, 0xC, 1, 2, 0xD, 0xB, 4, 6, 3, 0, 7, 9, 0xA];
+        assert_eq!(&sbox_inv, PRESENT.sbox_inv());
+        assert_eq!(&S, PRESENT.sbox());
+        assert_eq!(&P, PRESENT.permutation());
+        for (i, &pi) in PRESENT.permutation_inv().iter().enumerate() {
+            // Bit 4j + k goes to bit 16k + j, so bit 16k + j comes back from 4j + k.
+            assert_eq!((i % 16) * 4 + i / 16, pi as usize);
+            assert_eq!(i as u8, PRESENT.permutation_inv()[P[i] as usize]);
+        }
+    }
+
+    #[test]
+    fn test_present() {
+        let key = [0x5a; 16];
+        let cipher = Cipher::new(PRESENT, &present80::Key::new(&key), NUM_ROUNDS);
+        let expected = present80::Cipher::new(present80::Key::new(&key));
+        assert_eq!(NUM_ROUNDS, cipher.num_rounds());
+        assert_eq!(
+            expected.encrypt_state(PLAINTEXT),
+            cipher.encrypt_state(PLAINTEXT)
+        );
+
+        let cipher = Cipher::new(PRESENT, &present128::Key::new(&key), NUM_ROUNDS);
+        let expected = present128::Cipher::new(present128::Key::new(&key));
+        assert_eq!(
+            expected.encrypt_state(PLAINTEXT),
+            cipher.encrypt_state(PLAINTEXT)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // PRESENT's S-box with a permutation that rotates the state left by a bit.
+        let mut permutation = [0u8; STATE_SIZE_IN_BITS];
+        for (i, pi) in permutation.iter_mut().enumerate() {
+            *pi = ((i + 1) % STATE_SIZE_IN_BITS) as u8;
+        }
+        let spn = Spn::new(S, permutation).unwrap();
+        assert_eq!(PLAINTEXT.rotate_left(1), spn.p_layer(PLAINTEXT));
+
+        for &num_rounds in [0, 1, 40].iter() {
+            let cipher = Cipher::new(spn, &present80::Key::new(&[0x42; 10]), num_rounds);
+            let encrypted = cipher.encrypt_state(PLAINTEXT);
+            assert_eq!(PLAINTEXT, cipher.decrypt_state(encrypted));
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut sbox = S;
+        sbox[0] = sbox[1];
+        assert_eq!(
+            Err(Error::InvalidSBox(analysis::Error::NotBijective)),
+            Spn::new(sbox, P)
+        );
+
+        let mut permutation = P;
+        permutation[5] = 64;
+        assert_eq!(
+            Err(Error::InvalidPermutationEntry(64)),
+            Spn::new(S, permutation)
+        );
+        permutation[5] = permutation[6];
+        assert_eq!(
+            Err(Error::PermutationNotBijective),
+            Spn::new(S, permutation)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use super::spn::Spn;
+
+// Every intermediate state of one encryption, laid out like the round-by-round test vectors in
+// the appendix of the PRESENT paper.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Round {
+    pub round: usize,
+    pub round_key: u64,
+    pub after_add_round_key: u64,
+    pub after_s_box_layer: u64,
+    pub after_p_layer: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    pub plaintext: u64,
+    pub rounds: Vec<Round>,
+    pub final_round_key: u64,
+    pub ciphertext: u64,
+}
+
+pub(crate) fn encrypt_traced(spn: &Spn, plaintext: u64, round_keys: &[u64]) -> Trace {
+    let (final_round_key, round_keys) = round_keys.split_last().unwrap();
+    let mut rounds = Vec::with_capacity(round_keys.len());
+    let mut state = plaintext;
+
+    for (i, round_key) in round_keys.iter().enumerate() {
+        let after_add_round_key = super::add_round_key(state, *round_key);
+        let after_s_box_layer = spn.s_box_layer(after_add_round_key);
+        let after_p_layer = spn.p_layer(after_s_box_layer);
+        rounds.push(Round {
+            round: i + 1,
+            round_key: *round_key,
+            after_add_round_key,
+            after_s_box_layer,
+            after_p_layer,
+        });
+        state = after_p_layer;
+    }
+
+    Trace {
+        plaintext,
+        ciphertext: super::add_round_key(state, *final_round_key),
+        rounds,
+        final_round_key: *final_round_key,
+    }
+}
+
+impl Trace {
+    pub fn to_table(&self) -> String {
+        let mut table = format!("plaintext   {:016x}\n", self.plaintext);
+        table.push_str("round  round key         add round key     s-box layer       p-layer\n");
+        for round in self.rounds.iter() {
+            table.push_str(&format!(
+                "{:<5}  {:016x}  {:016x}  {:016x}  {:016x}\n",
+                round.round,
+                round.round_key,
+                round.after_add_round_key,
+                round.after_s_box_layer,
+                round.after_p_layer
+            ));
+        }
+        table.push_str(&format!(
+            "{:<5}  {:016x}  {:016x}\n",
+            self.rounds.len() + 1,
+            self.final_round_key,
+            self.ciphertext
+        ));
+        table.push_str(&format!("ciphertext  {:016x}\n", self.ciphertext));
+        table
+    }
+
+    // States are hex strings, since JSON numbers cannot hold every u64 exactly.
+    pub fn to_json(&self) -> String {
+        let rounds: Vec<String> = self
+            .rounds
+            .iter()
+            .map(|round| {
+                format!(
+                    "{{\"round\":{},\"round_key\":\"{:016x}\",\"after_add_round_key\":\"{:016x}\",\"after_s_box_layer\":\"{:016x}\",\"after_p_layer\":\"{:016x}\"}}",
+                    round.round,
+                    round.round_key,
+                    round.after_add_round_key,
+                    round.after_s_box_layer,
+                    round.after_p_layer
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"plaintext\":\"{:016x}\",\"rounds\":[{}],\"final_round_key\":\"{:016x}\",\"ciphertext\":\"{:016x}\"}}",
+            self.plaintext,
+            rounds.join(","),
+            self.final_round_key,
+            self.ciphertext
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{present128, present80, BlockCipher};
+
+    #[test]
+    fn test_encrypt_traced() {
+        let cipher = present80::Cipher::new(present80::Key::new(&[0; 10]));
+        let trace = cipher.encrypt_traced(0);
+        assert_eq!(0, trace.plaintext);
+        assert_eq!(31, trace.rounds.len());
+        assert_eq!(0x5579c1387b228445, trace.ciphertext);
+
+        let first = &trace.rounds[0];
+        assert_eq!(0, first.round_key);
+        assert_eq!(0xcccccccccccccccc, first.after_s_box_layer);
+        assert_eq!(0xffffffff00000000, first.after_p_layer);
+        assert_eq!(
+            first.after_p_layer ^ trace.rounds[1].round_key,
+            trace.rounds[1].after_add_round_key
+        );
+
+        let cipher = present128::Cipher::new(present128::Key::new(&[0xff; 16]));
+        let trace = present128::encrypt_traced(&[0xff; 8], present128::Key::new(&[0xff; 16]));
+        assert_eq!(!0, trace.plaintext);
+        assert_eq!(cipher.encrypt_state(!0), trace.ciphertext);
+    }
+
+    #[test]
+    fn test_to_table() {
+        let trace = present80::encrypt_traced(&[0; 8], present80::Key::new(&[0; 10]));
+        let table = trace.to_table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(35, lines.len());
+        assert_eq!("plaintext   0000000000000000", lines[0]);
+        assert_eq!(
+            "1      0000000000000000  0000000000000000  cccccccccccccccc  ffffffff00000000",
+            lines[2]
+        );
+        assert_eq!("ciphertext  5579c1387b228445", lines[34]);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let trace = present80::encrypt_traced(&[0; 8], present80::Key::new(&[0; 10]));
+        let json = trace.to_json();
+        assert!(json.starts_with("{\"plaintext\":\"0000000000000000\",\"rounds\":[{\"round\":1,\"round_key\":\"0000000000000000\",\"after_add_round_key\":\"0000000000000000\",\"after_s_box_layer\":\"cccccccccccccccc\",\"after_p_layer\":\"ffffffff00000000\"},{\"round\":2,"));
+        assert!(json.ends_with("\"ciphertext\":\"5579c1387b228445\"}"));
+        assert_eq!(31, json.matches("\"round\":").count());
+    }
+}
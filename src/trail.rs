@@ -0,0 +1,379 @@
+use super::analysis::{self, SBOX_SIZE};
+use super::{p_layer, S};
+
+const NUM_SBOXES: usize = 16;
+
+// Weights are compared with a little slack, since they are sums of logarithms.
+const EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Differential,
+    Linear,
+}
+
+// A round takes the input difference or mask through the S-box layer to its output, before the
+// permutation. The weight is -log2 of the probability or of the absolute correlation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Round {
+    pub input: u64,
+    pub output: u64,
+    pub weight: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trail {
+    pub kind: Kind,
+    pub rounds: Vec<Round>,
+}
+
+impl Trail {
+    pub fn weight(&self) -> f64 {
+        self.rounds.iter().map(|round| round.weight).sum()
+    }
+
+    // The probability of a differential characteristic, or the absolute correlation of a
+    // linear one.
+    pub fn probability(&self) -> f64 {
+        (-self.weight()).exp2()
+    }
+
+    pub fn active_sboxes(&self) -> usize {
+        self.rounds
+            .iter()
+            .map(|round| active_nibbles(round.input).count())
+            .sum()
+    }
+
+    // The difference or mask after the permutation of the last round.
+    pub fn output(&self) -> u64 {
+        self.rounds.last().map_or(0, |round| p_layer(round.output))
+    }
+}
+
+fn nibble(state: u64, i: usize) -> usize {
+    ((state >> (4 * i)) & 0xf) as usize
+}
+
+fn active_nibbles(state: u64) -> impl Iterator<Item = usize> {
+    (0..NUM_SBOXES).filter(move |&i| nibble(state, i) != 0)
+}
+
+// The weight of every S-box transition, or None where the DDT or LAT entry is zero.
+fn transition_weights(kind: Kind) -> [[Option<f64>; SBOX_SIZE]; SBOX_SIZE] {
+    let (table, total) = match kind {
+        Kind::Differential => (analysis::ddt(&S), SBOX_SIZE as f64),
+        Kind::Linear => (analysis::lat(&S), SBOX_SIZE as f64 / 2.0),
+    };
+    let table = table.expect("PRESENT's S-box is valid");
+
+    let mut weights = [[None; SBOX_SIZE]; SBOX_SIZE];
+    for (a, row) in table.iter().enumerate() {
+        for (b, &entry) in row.iter().enumerate() {
+            if entry != 0 {
+                weights[a][b] = Some(-(entry.abs() as f64 / total).log2());
+            }
+        }
+    }
+    weights
+}
+
+struct Search {
+    kind: Kind,
+    // The possible output nibbles of each nonzero input nibble, cheapest first.
+    transitions: Vec<Vec<(usize, f64)>>,
+    // The cheapest input nibble for each nonzero output nibble, used for the first round.
+    best_input: Vec<(usize, f64)>,
+    min_weight: f64,
+    // The best weight for each number of rounds searched so far.
+    bounds: Vec<f64>,
+    num_rounds: usize,
+    // Bits of which at least one must be set in the output, or 0 for any output.
+    target: u64,
+    estimate: f64,
+    rounds: Vec<Round>,
+    best: Option<Vec<Round>>,
+}
+
+impl Search {
+    fn new(kind: Kind) -> Search {
+        let weights = transition_weights(kind);
+
+        let mut transitions = vec![Vec::new(); SBOX_SIZE];
+        let mut best_input = vec![(0, f64::INFINITY); SBOX_SIZE];
+        for a in 1..SBOX_SIZE {
+            for b in 1..SBOX_SIZE {
+                if let Some(weight) = weights[a][b] {
+                    transitions[a].push((b, weight));
+                    if weight < best_input[b].1 {
+                        best_input[b] = (a, weight);
+                    }
+                }
+            }
+            transitions[a].sort_by(|x, y| x.1.partial_cmp(&y.1).unwrap());
+        }
+        let min_weight = transitions
+            .iter()
+            .flat_map(|t| t.iter().map(|&(_, weight)| weight))
+            .fold(f64::INFINITY, f64::min);
+
+        Search {
+            kind,
+            transitions,
+            best_input,
+            min_weight,
+            bounds: vec![0.0],
+            num_rounds: 0,
+            target: 0,
+            estimate: 0.0,
+            rounds: Vec::new(),
+            best: None,
+        }
+    }
+
+    // Matsui's algorithm: the bound on the weight is raised until a trail is found, pruning any
+    // partial trail that cannot beat it given the best trails over fewer rounds.
+    fn run(&mut self, num_rounds: usize) -> Trail {
+        self.extend_bounds(num_rounds);
+        self.trail()
+    }
+
+    fn extend_bounds(&mut self, num_rounds: usize) {
+        while self.bounds.len() <= num_rounds {
+            let n = self.bounds.len();
+            let weight = self.search(n).weight();
+            self.bounds.push(weight);
+        }
+    }
+
+    // The bounds for fewer rounds must already be known.
+    fn search(&mut self, num_rounds: usize) -> Trail {
+        self.num_rounds = num_rounds;
+        self.estimate = self.bounds[num_rounds - 1] + self.min_weight;
+        self.best = None;
+        while self.best.is_none() {
+            self.search_first_round(0, 0, 0, 0.0);
+            self.estimate += 1.0;
+        }
+        self.trail()
+    }
+
+    fn trail(&self) -> Trail {
+        Trail {
+            kind: self.kind,
+            rounds: self.best.clone().unwrap(),
+        }
+    }
+
+    // The bound on the weight of the rounds after this one.
+    fn remaining_bound(&self) -> f64 {
+        self.bounds[self.num_rounds - self.rounds.len() - 1]
+    }
+
+    fn prunes(&self, weight: f64) -> bool {
+        weight + self.remaining_bound() > self.estimate + EPSILON
+    }
+
+    // The input of the first round is free, so each active S-box takes its cheapest input.
+    fn search_first_round(&mut self, i: usize, input: u64, output: u64, weight: f64) {
+        if i == NUM_SBOXES {
+            if output != 0 {
+                self.next_round(input, output, weight, 0.0);
+            }
+            return;
+        }
+
+        self.search_first_round(i + 1, input, output, weight);
+        for b in 1..SBOX_SIZE {
+            let (a, w) = self.best_input[b];
+            if w.is_finite() && !self.prunes(weight + w) {
+                let shift = 4 * i;
+                let input = input | (a as u64) << shift;
+                let output = output | (b as u64) << shift;
+                self.search_first_round(i + 1, input, output, weight + w);
+            }
+        }
+    }
+
+    fn search_round(
+        &mut self,
+        input: u64,
+        active: &[usize],
+        output: u64,
+        weight: f64,
+        before: f64,
+    ) {
+        let (i, active) = match active.split_first() {
+            Some((&i, active)) => (i, active),
+            None => return self.next_round(input, output, weight, before),
+        };
+
+        // Every S-box still to come costs at least the cheapest transition.
+        let rest = active.len() as f64 * self.min_weight;
+        let a = nibble(input, i);
+        for k in 0..self.transitions[a].len() {
+            let (b, w) = self.transitions[a][k];
+            if self.prunes(before + weight + w + rest) {
+                break;
+            }
+            let output = output | (b as u64) << (4 * i);
+            self.search_round(input, active, output, weight + w, before);
+        }
+    }
+
+    // In the last round each active S-box takes its cheapest output.
+    fn last_round(&mut self, input: u64, before: f64) {
+        let mut output = 0u64;
+        let mut weight = 0.0;
+        for i in active_nibbles(input) {
+            let (b, w) = self.transitions[nibble(input, i)][0];
+            output |= (b as u64) << (4 * i);
+            weight += w;
+        }
+        self.next_round(input, output, weight, before);
+    }
+
+    fn next_round(&mut self, input: u64, output: u64, weight: f64, before: f64) {
+        self.rounds.push(Round {
+            input,
+            output,
+            weight,
+        });
+
+        let total = before + weight;
+        if self.rounds.len() == self.num_rounds {
+            let on_target = self.target == 0 || p_layer(output) & self.target != 0;
+            if on_target && total <= self.estimate + EPSILON {
+                // Only strictly better trails are worth looking for from now on.
+                self.estimate = total - EPSILON;
+                self.best = Some(self.rounds.clone());
+            }
+        } else if !self.prunes(total) {
+            let input = p_layer(output);
+            // The cheapest outputs of the last round may miss the target, so then it is
+            // searched like any other.
+            if self.rounds.len() + 1 == self.num_rounds && self.target == 0 {
+                self.last_round(input, total);
+            } else {
+                let active: Vec<usize> = active_nibbles(input).collect();
+                self.search_round(input, &active, 0, 0.0, total);
+            }
+        }
+
+        self.rounds.pop();
+    }
+}
+
+pub fn best_trail(kind: Kind, num_rounds: usize) -> Trail {
+    assert!(num_rounds > 0, "a trail needs at least one round");
+    Search::new(kind).run(num_rounds)
+}
+
+// Searches for the best trails into several targets, finding the bounds for fewer rounds only
+// once.
+pub struct TargetSearch {
+    search: Search,
+    num_rounds: usize,
+}
+
+impl TargetSearch {
+    pub fn new(kind: Kind, num_rounds: usize) -> TargetSearch {
+        assert!(num_rounds > 0, "a trail needs at least one round");
+        let mut search = Search::new(kind);
+        search.extend_bounds(num_rounds - 1);
+        TargetSearch { search, num_rounds }
+    }
+
+    // The best trail with at least one of the target bits set in its output.
+    pub fn best_trail_to(&mut self, target: u64) -> Trail {
+        assert!(target != 0, "a trail needs a target");
+        self.search.target = target;
+        self.search.search(self.num_rounds)
+    }
+}
+
+pub fn best_trail_to(kind: Kind, num_rounds: usize, target: u64) -> Trail {
+    TargetSearch::new(kind, num_rounds).best_trail_to(target)
+}
+
+pub fn best_differential_trail(num_rounds: usize) -> Trail {
+    best_trail(Kind::Differential, num_rounds)
+}
+
+pub fn best_linear_trail(num_rounds: usize) -> Trail {
+    best_trail(Kind::Linear, num_rounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every transition must be possible and the rounds must be joined by the permutation.
+    fn check(trail: &Trail, num_rounds: usize) {
+        let weights = transition_weights(trail.kind);
+        assert_eq!(num_rounds, trail.rounds.len());
+        for (i, round) in trail.rounds.iter().enumerate() {
+            let mut weight = 0.0;
+            for j in 0..NUM_SBOXES {
+                let (a, b) = (nibble(round.input, j), nibble(round.output, j));
+                assert_eq!(a == 0, b == 0);
+                if a != 0 {
+                    weight += weights[a][b].unwrap();
+                }
+            }
+            assert!((weight - round.weight).abs() < EPSILON);
+            if i > 0 {
+                assert_eq!(p_layer(trail.rounds[i - 1].output), round.input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_differential() {
+        // Every active S-box costs at least 2, and the PRESENT paper shows that any 5 rounds
+        // have at least 10 of them.
+        for &(num_rounds, weight, active_sboxes) in
+            [(1, 2.0, 1), (2, 4.0, 2), (3, 8.0, 4), (4, 12.0, 6)].iter()
+        {
+            let trail = best_differential_trail(num_rounds);
+            check(&trail, num_rounds);
+            assert_eq!(weight, trail.weight());
+            assert_eq!(weight, -trail.probability().log2());
+            assert_eq!(active_sboxes, trail.active_sboxes());
+        }
+    }
+
+    #[test]
+    fn test_linear() {
+        // Matches the PRESENT paper's bound of 2^-(2r-1) on the bias of an r-round trail.
+        for &(num_rounds, weight) in [(1, 1.0), (2, 2.0), (3, 4.0), (4, 6.0)].iter() {
+            let trail = best_linear_trail(num_rounds);
+            check(&trail, num_rounds);
+            assert_eq!(weight, trail.weight());
+        }
+    }
+
+    #[test]
+    fn test_best_trail_to() {
+        for &kind in [Kind::Differential, Kind::Linear].iter() {
+            for &num_rounds in [1, 3].iter() {
+                let best = best_trail(kind, num_rounds);
+                let mut search = TargetSearch::new(kind, num_rounds);
+                for &i in [0, 17, 42, 63].iter() {
+                    let trail = search.best_trail_to(1 << i);
+                    check(&trail, num_rounds);
+                    assert_ne!(0, trail.output() & 1 << i);
+                    assert!(trail.weight() >= best.weight());
+                    assert_eq!(best_trail_to(kind, num_rounds, 1 << i), trail);
+                }
+            }
+        }
+
+        // Aiming at bits the best trail already ends in costs nothing.
+        let best = best_differential_trail(2);
+        assert_eq!(
+            best.weight(),
+            best_trail_to(Kind::Differential, 2, best.output()).weight()
+        );
+    }
+}
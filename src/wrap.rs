@@ -0,0 +1,203 @@
+use std::error;
+use std::fmt;
+
+use super::present128;
+use super::BlockCipher;
+
+// PRESENT has a 64-bit block, so the RFC 3394 wrapping function is applied with 32-bit
+// semiblocks as in the TKW variant of NIST SP 800-38F.
+pub const SEMIBLOCK_SIZE_IN_BYTES: usize = 4;
+pub const WRAPPED_KEY_LENGTH_IN_BYTES: usize =
+    present128::KEY_LENGTH_IN_BYTES + SEMIBLOCK_SIZE_IN_BYTES;
+
+const ICV: u32 = 0xA6A6A6A6;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidLength,
+    IntegrityCheckFailed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidLength => write!(f, "invalid length"),
+            Error::IntegrityCheckFailed => write!(f, "integrity check failed"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+fn bytes_to_semiblock(bytes: &[u8]) -> u32 {
+    let mut semiblock = 0u32;
+    for byte in bytes.iter() {
+        semiblock = (semiblock << 8) | *byte as u32;
+    }
+    semiblock
+}
+
+fn semiblocks_to_bytes(semiblocks: &[u32]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(semiblocks.len() * SEMIBLOCK_SIZE_IN_BYTES);
+    for semiblock in semiblocks.iter() {
+        for i in 0..SEMIBLOCK_SIZE_IN_BYTES {
+            bytes.push((semiblock >> ((3 - i) * 8)) as u8);
+        }
+    }
+    bytes
+}
+
+pub fn wrap<C: BlockCipher>(kek: &C, data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < 2 * SEMIBLOCK_SIZE_IN_BYTES
+        || !data.len().is_multiple_of(SEMIBLOCK_SIZE_IN_BYTES)
+    {
+        return Err(Error::InvalidLength);
+    }
+
+    let mut r: Vec<u32> = data.chunks(SEMIBLOCK_SIZE_IN_BYTES)
+        .map(bytes_to_semiblock)
+        .collect();
+    let n = r.len();
+    let mut a = ICV;
+
+    for j in 0..6 {
+        for (i, semiblock) in r.iter_mut().enumerate() {
+            let t = (n * j + i + 1) as u32;
+            let b = kek.encrypt_state(((a as u64) << 32) | *semiblock as u64);
+            a = ((b >> 32) as u32) ^ t;
+            *semiblock = b as u32;
+        }
+    }
+
+    let mut wrapped = semiblocks_to_bytes(&[a]);
+    wrapped.extend_from_slice(&semiblocks_to_bytes(&r));
+    Ok(wrapped)
+}
+
+pub fn unwrap<C: BlockCipher>(kek: &C, data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < 3 * SEMIBLOCK_SIZE_IN_BYTES
+        || !data.len().is_multiple_of(SEMIBLOCK_SIZE_IN_BYTES)
+    {
+        return Err(Error::InvalidLength);
+    }
+
+    let mut a = bytes_to_semiblock(&data[..SEMIBLOCK_SIZE_IN_BYTES]);
+    let mut r: Vec<u32> = data[SEMIBLOCK_SIZE_IN_BYTES..]
+        .chunks(SEMIBLOCK_SIZE_IN_BYTES)
+        .map(bytes_to_semiblock)
+        .collect();
+    let n = r.len();
+
+    for j in (0..6).rev() {
+        for (i, semiblock) in r.iter_mut().enumerate().rev() {
+            let t = (n * j + i + 1) as u32;
+            let b = kek.decrypt_state((((a ^ t) as u64) << 32) | *semiblock as u64);
+            a = (b >> 32) as u32;
+            *semiblock = b as u32;
+        }
+    }
+
+    if a != ICV {
+        super::zeroize(&mut r);
+        return Err(Error::IntegrityCheckFailed);
+    }
+
+    // Converting only R leaves no copy of the key behind, as shifting out A would.
+    let unwrapped = semiblocks_to_bytes(&r);
+    super::zeroize(&mut r);
+    Ok(unwrapped)
+}
+
+pub fn wrap_key(
+    kek: &present128::Cipher,
+    key: &present128::Key,
+) -> [u8; WRAPPED_KEY_LENGTH_IN_BYTES] {
+    let wrapped = wrap(kek, &key.bytes[..]).expect("error wrapping key");
+
+    let mut bytes = [0u8; WRAPPED_KEY_LENGTH_IN_BYTES];
+    bytes.copy_from_slice(&wrapped[..]);
+    bytes
+}
+
+pub fn unwrap_key(kek: &present128::Cipher, data: &[u8]) -> Result<present128::Key, Error> {
+    if data.len() != WRAPPED_KEY_LENGTH_IN_BYTES {
+        return Err(Error::InvalidLength);
+    }
+
+    let mut unwrapped = unwrap(kek, data)?;
+    let key = present128::Key::new(&unwrapped[..]);
+    super::zeroize(&mut unwrapped);
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use super::*;
+    use present80;
+
+    #[test]
+    fn test_wrap_unwrap_key() {
+        let kek = present128::Cipher::new(present128::Key::new(&[0x5a; 16]));
+        let key = present128::Key::new(&[
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+        ]);
+
+        let wrapped = wrap_key(&kek, &key);
+        assert_ne!(&key.bytes[..], &wrapped[SEMIBLOCK_SIZE_IN_BYTES..]);
+
+        let unwrapped = unwrap_key(&kek, &wrapped[..]).unwrap();
+        assert_eq!(key.bytes, unwrapped.bytes);
+    }
+
+    // Computed with an independent implementation of PRESENT-128 and the RFC 3394 steps: A
+    // starts as 0xA6A6A6A6 and is XORed with the counter n * j + i after each encryption.
+    #[test]
+    fn test_wrap_known_answer() {
+        let kek = present128::Cipher::new(present128::Key::new(&[
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+        ]));
+        let key = hex::decode("00112233445566778899aabbccddeeff").unwrap();
+
+        let wrapped = wrap(&kek, &key).unwrap();
+        assert_eq!("968c1aa9e7bcc9930c7c0a470dac20f99cf49b61", hex::encode(&wrapped));
+        assert_eq!(key, unwrap(&kek, &wrapped).unwrap());
+    }
+
+    #[test]
+    fn test_unwrap_key_wrong_kek() {
+        let kek = present128::Cipher::new(present128::Key::new(&[0x5a; 16]));
+        let wrong_kek = present128::Cipher::new(present128::Key::new(&[0xa5; 16]));
+        let key = present128::Key::new(&[0xff; 16]);
+
+        let wrapped = wrap_key(&kek, &key);
+        assert_eq!(
+            Err(Error::IntegrityCheckFailed),
+            unwrap_key(&wrong_kek, &wrapped[..]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_unwrap_tampered() {
+        let kek = present80::Cipher::new(present80::Key::new(&[0x5a; 10]));
+        let data = [1u8, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+
+        let mut wrapped = wrap(&kek, &data).unwrap();
+        assert_eq!(data.len() + SEMIBLOCK_SIZE_IN_BYTES, wrapped.len());
+        assert_eq!(&data[..], &unwrap(&kek, &wrapped[..]).unwrap()[..]);
+
+        wrapped[7] ^= 1;
+        assert_eq!(Err(Error::IntegrityCheckFailed), unwrap(&kek, &wrapped[..]));
+    }
+
+    #[test]
+    fn test_wrap_invalid_length() {
+        let kek = present80::Cipher::new(present80::Key::new(&[0; 10]));
+
+        assert_eq!(Err(Error::InvalidLength), wrap(&kek, &[0u8; 4]));
+        assert_eq!(Err(Error::InvalidLength), wrap(&kek, &[0u8; 10]));
+        assert_eq!(Err(Error::InvalidLength), unwrap(&kek, &[0u8; 8]));
+        assert_eq!(Err(Error::InvalidLength), unwrap(&kek, &[0u8; 13]));
+    }
+}
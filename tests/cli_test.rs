@@ -0,0 +1,864 @@
+extern crate hex;
+extern crate present;
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Output, Stdio};
+use std::time::{Duration, SystemTime};
+
+fn present(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_present"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The command may exit without reading its input, so a broken pipe is fine.
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("present-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn encrypt_block(key: &str, key_length: &str) -> Output {
+    present(
+        &[
+            "encrypt",
+            "--key",
+            key,
+            "--key-length",
+            key_length,
+            "--mode",
+            "ecb",
+            "--padding",
+            "none",
+            "-I",
+            "hex",
+            "-O",
+            "hex",
+        ],
+        b"0000000000000000",
+    )
+}
+
+macro_rules! test_key_length {
+    ($name:ident, $k:expr, $l:expr, $e:expr, $w:expr) => {
+        #[test]
+        fn $name() {
+            let output = encrypt_block($k, $l);
+            assert!(output.status.success());
+
+            let expected = $e;
+            assert_eq!(expected, String::from_utf8(output.stdout).unwrap());
+
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            let warning: Option<&str> = $w;
+            match warning {
+                Some(warning) => assert!(stderr.contains(warning), "{}", stderr),
+                None => assert!(stderr.is_empty(), "{}", stderr),
+            }
+        }
+    };
+}
+
+test_key_length!(
+    test_auto_80,
+    "00000000000000000000",
+    "auto",
+    "5579c1387b228445",
+    None
+);
+
+test_key_length!(
+    test_auto_128,
+    "00000000000000000000000000000000",
+    "auto",
+    "96db702a2e6900af",
+    None
+);
+
+test_key_length!(
+    test_80,
+    "00000000000000000000",
+    "80",
+    "5579c1387b228445",
+    None
+);
+
+test_key_length!(
+    test_80_truncated,
+    "00000000000000000000000000000000",
+    "80",
+    "5579c1387b228445",
+    Some("provided key is 128 bits long and will be truncated to 80 bits")
+);
+
+test_key_length!(
+    test_128,
+    "00000000000000000000000000000000",
+    "128",
+    "96db702a2e6900af",
+    None
+);
+
+test_key_length!(
+    test_128_padded,
+    "00000000000000000000",
+    "128",
+    "96db702a2e6900af",
+    Some("provided key is 80 bits long and will be padded with zeroes to 128 bits")
+);
+
+#[test]
+fn test_auto_rejects_other_lengths() {
+    for key in [
+        "0000000000",
+        "000000000000000000000000",
+        "0000000000000000000000000000000000",
+    ]
+    .iter()
+    {
+        let output = encrypt_block(key, "auto");
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let expected = format!("provided key is {} bits long", key.len() * 4);
+        assert!(stderr.contains(&expected), "{}", stderr);
+    }
+}
+
+#[test]
+fn test_container_round_trip() {
+    let plaintext = b"attack at dawn";
+    for &(key, key_length) in [
+        ("00112233445566778899", "auto"),
+        ("00112233445566778899aabbccddeeff", "auto"),
+        ("00112233445566778899", "80"),
+        ("00112233445566778899aabbccddeeff", "128"),
+    ]
+    .iter()
+    {
+        let encrypted = present(&["encrypt", "-k", key, "-l", key_length], plaintext);
+        assert!(encrypted.status.success());
+
+        let decrypted = present(&["decrypt", "-k", key, "-l", key_length], &encrypted.stdout);
+        assert!(decrypted.status.success());
+        assert_eq!(&plaintext[..], &decrypted.stdout[..]);
+    }
+}
+
+// Written by the last release without the container format, which zero-padded bare ECB.
+#[test]
+fn test_raw_decrypts_old_output() {
+    let old = hex::decode("9f1eaa93f97526abca7b8664f23e044f9af11580fec1f530").unwrap();
+    let decrypted = present(&["decrypt", "-k", "00112233445566778899", "--raw"], &old);
+    assert!(decrypted.status.success());
+    assert_eq!(&b"old bare ECB output"[..], &decrypted.stdout[..]);
+
+    let encrypted = present(
+        &["encrypt", "-k", "00112233445566778899", "--raw"],
+        b"old bare ECB output",
+    );
+    assert_eq!(old, encrypted.stdout);
+}
+
+#[test]
+fn test_stats() {
+    let key = "00112233445566778899";
+    let plaintext = [0u8; 100];
+    for &mode in ["eax", "ctr"].iter() {
+        let encrypted = present(&["encrypt", "-k", key, "-m", mode, "--stats"], &plaintext);
+        assert!(encrypted.status.success());
+        let stderr = String::from_utf8(encrypted.stderr).unwrap();
+        assert!(stderr.starts_with("13 blocks in "), "{}", stderr);
+
+        // Progress is only drawn on a terminal.
+        let decrypted = present(
+            &["decrypt", "-k", key, "-m", mode, "--progress"],
+            &encrypted.stdout,
+        );
+        assert!(decrypted.status.success());
+        assert!(decrypted.stderr.is_empty());
+        assert_eq!(&plaintext[..], &decrypted.stdout[..]);
+    }
+}
+
+// Files are encrypted a chunk at a time, which must give the same result as a single pass.
+#[test]
+fn test_streamed_file() {
+    use present::{modes, present80};
+
+    let dir = temp_dir("streamed");
+    let path = dir.join("data");
+    let plaintext: Vec<u8> = (0..(1 << 16) + 13).map(|i| i as u8).collect();
+    fs::write(&path, &plaintext).unwrap();
+    let path = path.to_str().unwrap();
+
+    let cipher = present80::Cipher::new(present80::Key::new(&[0u8; 10]));
+    let iv = [1u8, 2, 3, 4, 5, 6, 7, 8];
+    for &mode in ["eax", "ecb", "cbc", "ctr", "cfb", "ofb"].iter() {
+        let mut args = vec!["encrypt", "-k", "00000000000000000000", "-m", mode];
+        if mode != "eax" && mode != "ecb" {
+            args.extend_from_slice(&["--iv", "0102030405060708"]);
+        }
+        let encrypted = present(&[&args[..], &[path]].concat(), b"");
+        assert!(encrypted.status.success());
+
+        let mut expected = plaintext.clone();
+        match mode {
+            "ecb" | "cbc" => expected = modes::pad(modes::Padding::Pkcs7, &expected).unwrap(),
+            _ => {}
+        }
+        match mode {
+            "eax" => expected = encrypted.stdout.clone(),
+            "ecb" => modes::ecb_encrypt(&cipher, &mut expected).unwrap(),
+            "cbc" => modes::cbc_encrypt(&cipher, &iv, &mut expected).unwrap(),
+            "ctr" => modes::ctr(&cipher, &iv, &mut expected).unwrap(),
+            "cfb" => modes::cfb_encrypt(&cipher, &iv, &mut expected).unwrap(),
+            _ => modes::ofb(&cipher, &iv, &mut expected).unwrap(),
+        }
+        assert_eq!(expected, encrypted.stdout, "{}", mode);
+
+        args[0] = "decrypt";
+        let decrypted = present(&args, &encrypted.stdout);
+        assert!(decrypted.status.success());
+        assert_eq!(plaintext, decrypted.stdout, "{}", mode);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_output_file() {
+    let dir = temp_dir("output");
+    let output = dir.join("out.enc");
+    let output = output.to_str().unwrap();
+
+    let result = present(
+        &["encrypt", "-k", "00112233445566778899", "-o", output],
+        b"hello",
+    );
+    assert!(result.status.success());
+    assert!(result.stdout.is_empty());
+
+    let result = present(
+        &["encrypt", "-k", "00112233445566778899", "-o", output],
+        b"world",
+    );
+    assert!(!result.status.success());
+
+    let result = present(&["decrypt", "-k", "00112233445566778899", output], b"");
+    assert_eq!(&b"hello"[..], &result.stdout[..]);
+
+    let result = present(
+        &[
+            "encrypt",
+            "-k",
+            "00112233445566778899",
+            "-o",
+            output,
+            "--force",
+        ],
+        b"world",
+    );
+    assert!(result.status.success());
+
+    let result = present(&["decrypt", "-k", "00112233445566778899", output], b"");
+    assert_eq!(&b"world"[..], &result.stdout[..]);
+
+    assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_output_file_removed_on_failure() {
+    let dir = temp_dir("failure");
+    let input = dir.join("in.enc");
+    let output = dir.join("out.txt");
+
+    let result = present(&["encrypt", "-k", "00112233445566778899"], b"hello");
+    fs::write(&input, &result.stdout).unwrap();
+
+    let result = present(
+        &[
+            "decrypt",
+            "-k",
+            "99887766554433221100",
+            "-o",
+            output.to_str().unwrap(),
+            input.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert!(!result.status.success());
+
+    assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_in_place() {
+    let dir = temp_dir("in-place");
+    let path = dir.join("file.txt");
+    fs::write(&path, b"hello").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    let path = path.to_str().unwrap();
+
+    let result = present(
+        &["encrypt", "-k", "00112233445566778899", "--in-place", path],
+        b"",
+    );
+    assert!(result.status.success());
+    assert_ne!(&b"hello"[..], &fs::read(path).unwrap()[..]);
+
+    let result = present(
+        &["decrypt", "-k", "00112233445566778899", "--in-place", path],
+        b"",
+    );
+    assert!(result.status.success());
+    assert_eq!(&b"hello"[..], &fs::read(path).unwrap()[..]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+
+    assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_recursive() {
+    let dir = temp_dir("recursive");
+    let input = dir.join("in");
+    let out = dir.join("out");
+    fs::create_dir_all(input.join("sub")).unwrap();
+    fs::write(input.join("a.log"), b"one").unwrap();
+    fs::write(input.join("sub").join("b.log"), b"two").unwrap();
+    // The copied mtime has to survive a read-only source.
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(input.join("a.log"))
+        .unwrap();
+    file.set_modified(modified).unwrap();
+    let mut permissions = file.metadata().unwrap().permissions();
+    permissions.set_readonly(true);
+    file.set_permissions(permissions).unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("a.log", input.join("link.log")).unwrap();
+
+    let result = present(
+        &[
+            "encrypt",
+            "-k",
+            "00112233445566778899",
+            "-r",
+            input.to_str().unwrap(),
+            "--out-dir",
+            out.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert!(result.status.success());
+    #[cfg(unix)]
+    assert!(String::from_utf8_lossy(&result.stderr).contains("skipping"));
+    assert_eq!(
+        modified,
+        fs::metadata(out.join("a.log")).unwrap().modified().unwrap()
+    );
+    assert!(fs::metadata(out.join("a.log"))
+        .unwrap()
+        .permissions()
+        .readonly());
+
+    let manifest = String::from_utf8(fs::read(out.join("MANIFEST")).unwrap()).unwrap();
+    let lines: Vec<&str> = manifest.lines().collect();
+    assert_eq!(2, lines.len());
+    assert!(lines[0].ends_with("  a.log"));
+    assert!(lines[1].ends_with("  sub/b.log"));
+
+    for &(path, line, plaintext) in
+        [("a.log", lines[0], "one"), ("sub/b.log", lines[1], "two")].iter()
+    {
+        let path = out.join(path);
+        let path = path.to_str().unwrap();
+
+        let result = present(&["decrypt", "-k", "00112233445566778899", path], b"");
+        assert_eq!(plaintext.as_bytes(), &result.stdout[..]);
+
+        let verify = |manifest: bool| {
+            let mut args = vec!["verify", "-k", "00112233445566778899", "-t", &line[..16]];
+            if manifest {
+                args.push("--manifest");
+            }
+            args.push(path);
+            present(&args, b"").status.success()
+        };
+        assert!(verify(true));
+        // The manifest has its own MAC key.
+        assert!(!verify(false));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_recursive_failure() {
+    let dir = temp_dir("recursive-failure");
+    let input = dir.join("in");
+    let out = dir.join("out");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.log"), b"12345678").unwrap();
+    fs::write(input.join("b.log"), b"123").unwrap();
+
+    // Without padding only whole blocks can be encrypted, so b.log fails.
+    let result = present(
+        &[
+            "encrypt",
+            "-k",
+            "00112233445566778899",
+            "-m",
+            "ecb",
+            "--padding",
+            "none",
+            "-r",
+            input.to_str().unwrap(),
+            "--out-dir",
+            out.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("b.log"));
+
+    let mut names: Vec<String> = fs::read_dir(&out)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(vec!["a.log"], names);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_text_formats() {
+    for format in ["hex", "base64", "base64url", "base32", "armor"].iter() {
+        let encrypted = present(
+            &["encrypt", "-k", "00112233445566778899", "-O", format],
+            b"hello",
+        );
+        assert!(encrypted.status.success());
+        assert!(encrypted.stdout.is_ascii());
+
+        // Wrapped lines, as when pasted into a ticket. Armor is wrapped already.
+        let line_length = if *format == "armor" { 1000 } else { 20 };
+        let mut wrapped: Vec<u8> = Vec::new();
+        for line in encrypted.stdout.chunks(line_length) {
+            wrapped.extend_from_slice(line);
+            wrapped.push(b'\n');
+        }
+
+        let decrypted = present(
+            &["decrypt", "-k", "00112233445566778899", "-I", format],
+            &wrapped,
+        );
+        assert!(decrypted.status.success(), "{}", format);
+        assert_eq!(&b"hello"[..], &decrypted.stdout[..]);
+    }
+}
+
+#[test]
+fn test_key_file_formats() {
+    let dir = temp_dir("key-formats");
+    let expected = encrypt_block("00112233445566778899", "auto").stdout;
+
+    for &(format, contents) in [
+        ("hex", "0011 2233 4455\n6677 8899\n"),
+        ("base64", "ABEiM0RVZneImQ==\n"),
+        ("base32", "AAISEM2EKVTHPCEZ\n"),
+        (
+            "armor",
+            "-----BEGIN PRESENT KEY-----\nABEiM0RVZneImQ==\n-----END PRESENT KEY-----\n",
+        ),
+    ]
+    .iter()
+    {
+        let path = dir.join(format);
+        fs::write(&path, contents).unwrap();
+
+        let output = present(
+            &[
+                "encrypt",
+                "-K",
+                path.to_str().unwrap(),
+                "-f",
+                format,
+                "--mode",
+                "ecb",
+                "--padding",
+                "none",
+                "-I",
+                "hex",
+                "-O",
+                "hex",
+            ],
+            b"0000000000000000",
+        );
+        assert!(output.status.success(), "{}", format);
+        assert_eq!(expected, output.stdout);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_keygen() {
+    let dir = temp_dir("keygen");
+
+    for &(key_length, format, size) in [
+        ("80", "hex", 20),
+        ("128", "hex", 32),
+        ("80", "binary", 10),
+        ("128", "binary", 16),
+    ]
+    .iter()
+    {
+        let result = present(&["keygen", "-l", key_length, "-O", format], b"");
+        assert!(result.status.success());
+        assert_eq!(size, result.stdout.len());
+    }
+
+    let path = dir.join("key");
+    let path = path.to_str().unwrap();
+    let result = present(&["keygen", "-l", "80", "-O", "armor", "-o", path], b"");
+    assert!(result.status.success());
+
+    let armored = String::from_utf8(fs::read(path).unwrap()).unwrap();
+    assert!(armored.starts_with("-----BEGIN PRESENT KEY-----\nKey-Size: 80\n"));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+
+    let encrypted = present(&["encrypt", "-K", path, "-f", "armor"], b"hello");
+    let decrypted = present(&["decrypt", "-K", path, "-f", "armor"], &encrypted.stdout);
+    assert_eq!(&b"hello"[..], &decrypted.stdout[..]);
+
+    let inspected = present(&["inspect"], &encrypted.stdout);
+    assert!(String::from_utf8(inspected.stdout)
+        .unwrap()
+        .contains("key size: 80 bits"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_key_env() {
+    let expected = encrypt_block("00112233445566778899", "auto").stdout;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_present"))
+        .args(["encrypt", "--key-env", "PRESENT_TEST_KEY", "--mode", "ecb"])
+        .args(["--padding", "none", "-I", "hex", "-O", "hex", "-"])
+        .env("PRESENT_TEST_KEY", "00112233445566778899\n")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(b"0000000000000000")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(expected, output.stdout);
+}
+
+#[test]
+fn test_key_fd_and_trailing_newline() {
+    let dir = temp_dir("key-fd");
+    let input = dir.join("input");
+    fs::write(&input, b"0000000000000000").unwrap();
+    let key_file = dir.join("key");
+    fs::write(&key_file, b"00112233445566778899\n").unwrap();
+
+    let expected = encrypt_block("00112233445566778899", "auto").stdout;
+    let args = [
+        "--mode",
+        "ecb",
+        "--padding",
+        "none",
+        "-I",
+        "hex",
+        "-O",
+        "hex",
+        "-f",
+        "hex",
+        input.to_str().unwrap(),
+    ];
+
+    let mut from_fd: Vec<&str> = vec!["encrypt", "--key-fd", "0"];
+    from_fd.extend(args.iter());
+    let output = present(&from_fd, b"00112233445566778899\n");
+    assert!(output.status.success());
+    assert_eq!(expected, output.stdout);
+
+    let mut from_file: Vec<&str> = vec!["encrypt", "-K", key_file.to_str().unwrap()];
+    from_file.extend(args.iter());
+    let output = present(&from_file, b"");
+    assert!(output.status.success());
+    assert_eq!(expected, output.stdout);
+
+    let expected = present(
+        &["mac", "-k", "00112233445566778899", input.to_str().unwrap()],
+        b"",
+    );
+    assert!(expected.status.success());
+    let output = present(
+        &["mac", "--key-fd", "0", "-f", "hex", input.to_str().unwrap()],
+        b"00112233445566778899\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(expected.stdout, output.stdout);
+
+    for fd in ["1", "2"].iter() {
+        let output = present(&["mac", "--key-fd", fd, input.to_str().unwrap()], b"");
+        assert!(!output.status.success());
+    }
+
+    // A descriptor that is not open is reported instead of being read.
+    let output = present(&["mac", "--key-fd", "97", input.to_str().unwrap()], b"");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("file descriptor 97"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_analyze_sbox() {
+    let result = present(&["analyze", "sbox", "-t", "ddt"], b"");
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout.starts_with("DDT\n"));
+    assert!(
+        stdout.contains("\n  1   0   0   0   4   0   0   0   4   0   4   0   0   0   4   0   0\n")
+    );
+
+    let result = present(&["analyze", "sbox", "-f", "csv"], b"");
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout.starts_with("table,input,0,1,2,3,4,5,6,7,8,9,a,b,c,d,e,f\nddt,0,16,"));
+    assert!(stdout.contains("\nlat,0,8,0,"));
+    assert!(stdout.contains("\nbct,0,16,16,"));
+    assert!(stdout.ends_with("branch,differential,3\nbranch,linear,2\n"));
+
+    let result = present(
+        &["analyze", "sbox", "-s", "0123456789abcdef", "-t", "branch"],
+        b"",
+    );
+    assert_eq!(
+        "Differential branch number: 2\nLinear branch number: 2\n",
+        String::from_utf8(result.stdout).unwrap()
+    );
+
+    let result = present(
+        &["analyze", "sbox", "-s", "0123456789abcdee", "-t", "bct"],
+        b"",
+    );
+    assert!(!result.status.success());
+}
+
+#[test]
+fn test_analyze_trail() {
+    let result = present(&["analyze", "trail", "-r", "2"], b"");
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout
+        .ends_with("Best differential trail over 2 rounds: probability 2^-4, 2 active S-boxes\n"));
+
+    let result = present(&["analyze", "trail", "-r", "2", "-k", "linear"], b"");
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        stdout.ends_with("Best linear trail over 2 rounds: correlation 2^-2, 2 active S-boxes\n")
+    );
+
+    assert!(!present(&["analyze", "trail", "-r", "32"], b"")
+        .status
+        .success());
+}
+
+#[test]
+fn test_analyze_experiment() {
+    let args = [
+        "analyze",
+        "experiment",
+        "-r",
+        "2",
+        "--keys",
+        "4",
+        "--samples",
+        "4096",
+        "--seed",
+        "7",
+    ];
+    let result = present(&args, b"");
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout.contains("from 16384 samples\n"));
+    assert!(stdout.ends_with("Best differential trail predicts probability 2^-4\n"));
+    assert_eq!(
+        stdout,
+        String::from_utf8(present(&args, b"").stdout).unwrap()
+    );
+
+    let result = present(
+        &[
+            "analyze",
+            "experiment",
+            "-r",
+            "1",
+            "--input",
+            "b",
+            "--output",
+            "0",
+            "--samples",
+            "16",
+        ],
+        b"",
+    );
+    assert_eq!(
+        "input   000000000000000b\noutput  0000000000000000\nEstimated probability 0 from 1024 samples\n",
+        String::from_utf8(result.stdout).unwrap()
+    );
+}
+
+#[test]
+fn test_analyze_integral() {
+    let result = present(
+        &["analyze", "integral", "-r", "4", "-a", "0", "--seed", "1"],
+        b"",
+    );
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout.starts_with("active    000000000000000f\nbalanced  ffffffffffffffff\n"));
+    assert!(stdout.ends_with(
+        "64 of 64 output bits balanced after 4 rounds over 16 trials of 2^4 plaintexts\n"
+    ));
+
+    let result = present(
+        &[
+            "analyze", "integral", "-r", "6", "-c", "f0", "--trials", "4",
+        ],
+        b"",
+    );
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout.starts_with("active    00000000000000f0\n"));
+    assert!(!stdout.contains("balanced  ffffffffffffffff"));
+
+    assert!(
+        !present(&["analyze", "integral", "-r", "4", "-a", "16"], b"")
+            .status
+            .success()
+    );
+    assert!(!present(&["analyze", "integral", "-r", "4"], b"")
+        .status
+        .success());
+}
+
+#[test]
+fn test_analyze_attack() {
+    let key = "0123456789abcdef5aa5";
+    let result = present(
+        &["analyze", "attack", "-r", "3", "-K", key, "--seed", "1"],
+        b"",
+    );
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(stdout.starts_with(
+        "trail  input             output            weight
+"
+    ));
+    assert!(stdout.contains(&format!(
+        "recovered key   {}\nsecret key      {}\n",
+        key, key
+    )));
+    assert!(stdout.contains("Recovered the key of 3 rounds with "));
+
+    assert!(!present(&["analyze", "attack", "-r", "1"], b"")
+        .status
+        .success());
+    assert!(
+        !present(&["analyze", "attack", "-r", "3", "-K", "0123"], b"")
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn test_trace() {
+    let key = "00000000000000000000";
+    let args = [
+        "encrypt",
+        "-k",
+        key,
+        "-m",
+        "ecb",
+        "--padding",
+        "none",
+        "--trace",
+    ];
+    let result = present(&args, &[0; 8]);
+    assert!(result.status.success());
+    assert_eq!(
+        &[0x55, 0x79, 0xc1, 0x38, 0x7b, 0x22, 0x84, 0x45],
+        &result.stdout[..]
+    );
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains(
+        "\n2      c000000000000000  3fffffff00000000  b2222222cccccccc  80ff00ffff008000\n"
+    ));
+    assert!(stderr.ends_with("ciphertext  5579c1387b228445\n"));
+
+    let result = present(
+        &[
+            "encrypt",
+            "-k",
+            key,
+            "-m",
+            "ecb",
+            "--trace",
+            "--trace-format",
+            "json",
+        ],
+        &[0; 8],
+    );
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(2, lines.len());
+    assert!(lines[0].ends_with("\"ciphertext\":\"5579c1387b228445\"}"));
+    assert!(lines[1].starts_with("{\"plaintext\":\"0808080808080808\""));
+
+    assert!(!present(&["encrypt", "-k", key, "--trace"], &[0; 8])
+        .status
+        .success());
+}
@@ -0,0 +1,104 @@
+extern crate hex;
+extern crate present;
+
+use present::{gift64, modes};
+
+// Test vectors from the GIFT reference implementation.
+macro_rules! test_encrypt_or_decrypt {
+    ($name:ident, $f:ident, $i:expr, $k: expr, $e:expr) => {
+        #[test]
+        fn $name() {
+            let input = hex::decode($i).unwrap();
+            let key_bytes = hex::decode($k).unwrap();
+            let key = gift64::Key::new(&key_bytes[..]);
+
+            let crypted = gift64::$f(&input[..], key);
+            let output = hex::encode_upper(&crypted[..]);
+
+            let expected = $e;
+            assert_eq!(expected, output);
+        }
+    };
+}
+
+macro_rules! test_block {
+    ($enc:ident, $dec:ident, $k:expr, $p:expr, $c:expr) => {
+        test_encrypt_or_decrypt!($enc, encrypt_block, $p, $k, $c);
+
+        test_encrypt_or_decrypt!($dec, decrypt_block, $c, $k, $p);
+    };
+}
+
+macro_rules! test_ecb {
+    ($enc:ident, $dec:ident, $k:expr, $p:expr, $c:expr) => {
+        test_encrypt_or_decrypt!($enc, ecb_encrypt, $p, $k, $c);
+
+        test_encrypt_or_decrypt!($dec, ecb_decrypt, $c, $k, $p);
+    };
+}
+
+macro_rules! test_par_ecb {
+    ($enc:ident, $dec:ident, $k:expr, $p:expr, $c:expr) => {
+        test_encrypt_or_decrypt!($enc, par_ecb_encrypt, $p, $k, $c);
+
+        test_encrypt_or_decrypt!($dec, par_ecb_decrypt, $c, $k, $p);
+    };
+}
+
+test_block!(
+    test_encrypt_block1,
+    test_decrypt_block1,
+    "00000000000000000000000000000000",
+    "0000000000000000",
+    "F62BC3EF34F775AC"
+);
+
+test_block!(
+    test_encrypt_block2,
+    test_decrypt_block2,
+    "FEDCBA9876543210FEDCBA9876543210",
+    "FEDCBA9876543210",
+    "C1B71F66160FF587"
+);
+
+test_block!(
+    test_encrypt_block3,
+    test_decrypt_block3,
+    "BD91731EB6BC2713A1F9F6FFC75044E7",
+    "C450C7727A9B8A7D",
+    "E3272885FA94BA8B"
+);
+
+test_ecb!(
+    test_ecb_encrypt1,
+    test_ecb_decrypt1,
+    "FEDCBA9876543210FEDCBA9876543210",
+    "FEDCBA9876543210FEDCBA9876543210",
+    "C1B71F66160FF587C1B71F66160FF587"
+);
+
+test_par_ecb!(
+    test_par_ecb_encrypt1,
+    test_par_ecb_decrypt1,
+    "FEDCBA9876543210FEDCBA9876543210",
+    "FEDCBA9876543210FEDCBA9876543210",
+    "C1B71F66160FF587C1B71F66160FF587"
+);
+
+#[test]
+fn test_cbc() {
+    let key_bytes = hex::decode("BD91731EB6BC2713A1F9F6FFC75044E7").unwrap();
+    let cipher = gift64::Cipher::new(gift64::Key::new(&key_bytes[..]));
+    let iv = [0u8; 8];
+
+    // With a zero IV, the first block of CBC is the block cipher itself.
+    let mut data = hex::decode("C450C7727A9B8A7DC450C7727A9B8A7D").unwrap();
+    modes::cbc_encrypt(&cipher, &iv, &mut data).unwrap();
+    assert_eq!("E3272885FA94BA8B", hex::encode_upper(&data[..8]));
+
+    modes::cbc_decrypt(&cipher, &iv, &mut data).unwrap();
+    assert_eq!(
+        "C450C7727A9B8A7DC450C7727A9B8A7D",
+        hex::encode_upper(&data[..])
+    );
+}
@@ -0,0 +1,103 @@
+extern crate hex;
+extern crate present;
+
+use present::present128;
+
+macro_rules! test_encrypt_or_decrypt {
+    ($name:ident, $f:ident, $i:expr, $k: expr, $e:expr) => {
+        #[test]
+        fn $name() {
+            let input = hex::decode($i).unwrap();
+            let key_bytes = hex::decode($k).unwrap();
+            let key = present128::Key::new(&key_bytes[..]);
+
+            let crypted = present128::$f(&input[..], key);
+            let output = hex::encode_upper(&crypted[..]);
+
+            let expected = $e;
+            assert_eq!(expected, output);
+        }
+    }
+}
+
+macro_rules! test_block {
+    ($enc:ident, $dec:ident, $k:expr, $p:expr, $c:expr) => {
+        test_encrypt_or_decrypt!($enc, encrypt_block, $p, $k, $c);
+
+        test_encrypt_or_decrypt!($dec, decrypt_block, $c, $k, $p);
+    };
+}
+
+macro_rules! test_ecb {
+    ($enc:ident, $dec:ident, $k:expr, $p:expr, $c:expr) => {
+        test_encrypt_or_decrypt!($enc, ecb_encrypt, $p, $k, $c);
+
+        test_encrypt_or_decrypt!($dec, ecb_decrypt, $c, $k, $p);
+    };
+}
+
+macro_rules! test_par_ecb {
+    ($enc:ident, $dec:ident, $k:expr, $p:expr, $c:expr) => {
+        test_encrypt_or_decrypt!($enc, par_ecb_encrypt, $p, $k, $c);
+
+        test_encrypt_or_decrypt!($dec, par_ecb_decrypt, $c, $k, $p);
+    };
+}
+
+test_block!(
+    test_encrypt_block1,
+    test_decrypt_block1,
+    "00000000000000000000000000000000",
+    "0000000000000000",
+    "96DB702A2E6900AF"
+);
+
+test_block!(
+    test_encrypt_block2,
+    test_decrypt_block2,
+    "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+    "0000000000000000",
+    "13238C710272A5D8"
+);
+
+test_block!(
+    test_encrypt_block3,
+    test_decrypt_block3,
+    "00000000000000000000000000000000",
+    "FFFFFFFFFFFFFFFF",
+    "3C6019E5E5EDD563"
+);
+
+test_block!(
+    test_encrypt_block4,
+    test_decrypt_block4,
+    "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+    "FFFFFFFFFFFFFFFF",
+    "628D9FBD4218E5B4"
+);
+
+test_ecb!(
+    test_ecb_encrypt1,
+    test_ecb_decrypt1,
+    "00000000000000000000000000000000",
+    "0000000000000000FFFFFFFFFFFFFFFF",
+    "96DB702A2E6900AF3C6019E5E5EDD563"
+);
+
+test_par_ecb!(
+    test_par_ecb_encrypt1,
+    test_par_ecb_decrypt1,
+    "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+    "0000000000000000FFFFFFFFFFFFFFFF",
+    "13238C710272A5D8628D9FBD4218E5B4"
+);
+
+#[test]
+fn test_decrypt_block_on_the_fly() {
+    let ciphertext = hex::decode("628D9FBD4218E5B4").unwrap();
+    let key = present128::Key::new(&[0xff; 16]);
+    let last_round_key = present128::key_at_round(key, present::NUM_ROUNDS + 1);
+
+    let decrypted = present128::decrypt_block_on_the_fly(&ciphertext[..], last_round_key);
+    assert_eq!("FFFFFFFFFFFFFFFF", hex::encode_upper(&decrypted[..]));
+}
@@ -143,3 +143,13 @@ test_par_ecb!(
     "0000000000000000FFFFFFFFFFFFFFFF",
     "E72C46C0F59450493333DCD3213210D2"
 );
+
+#[test]
+fn test_decrypt_block_on_the_fly() {
+    let ciphertext = hex::decode("3333DCD3213210D2").unwrap();
+    let key = present80::Key::new(&[0xff; 10]);
+    let last_round_key = present80::key_at_round(key, present::NUM_ROUNDS + 1);
+
+    let decrypted = present80::decrypt_block_on_the_fly(&ciphertext[..], last_round_key);
+    assert_eq!("FFFFFFFFFFFFFFFF", hex::encode_upper(&decrypted[..]));
+}